@@ -0,0 +1,84 @@
+// antialiasing.rs
+#![allow(dead_code)]
+
+/// Sample offsets are stored in 1/16 of a pixel, relative to the pixel center
+pub const SAMPLE_GRID: i32 = 16;
+
+/// How many samples each pixel keeps and how often they are shaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    /// One sample at the pixel center
+    None,
+    /// Multisampling with 2, 4 or 8 samples: coverage and depth per sample,
+    /// but the fragment shader runs once per pixel
+    Msaa(u32),
+    /// Ordered-grid supersampling with `n * n` samples, every one of them shaded
+    Ssaa(u32),
+}
+
+/// Where the samples of a pixel sit and whether each one gets its own shader run
+pub struct SamplePattern {
+    pub offsets: Vec<(i32, i32)>,
+    pub per_sample_shading: bool,
+}
+
+impl AntiAliasing {
+    /// MSAA with 2, 4 or 8 samples, 1 turns it off
+    pub fn msaa(samples: u32) -> Option<Self> {
+        match samples {
+            1 => Some(AntiAliasing::None),
+            2 | 4 | 8 => Some(AntiAliasing::Msaa(samples)),
+            _ => None,
+        }
+    }
+
+    /// SSAA grids from 2x2 up to 4x4, 1 turns it off
+    pub fn ssaa(factor: u32) -> Option<Self> {
+        match factor {
+            1 => Some(AntiAliasing::None),
+            2..=4 => Some(AntiAliasing::Ssaa(factor)),
+            _ => None,
+        }
+    }
+
+    pub fn pattern(self) -> SamplePattern {
+        match self {
+            AntiAliasing::None => SamplePattern {
+                offsets: vec![(0, 0)],
+                per_sample_shading: false,
+            },
+            // The standard D3D rotated patterns, they resolve near-vertical and
+            // near-horizontal edges better than a regular grid
+            AntiAliasing::Msaa(2) => SamplePattern {
+                offsets: vec![(4, 4), (-4, -4)],
+                per_sample_shading: false,
+            },
+            AntiAliasing::Msaa(4) => SamplePattern {
+                offsets: vec![(-2, -6), (6, -2), (-6, 2), (2, 6)],
+                per_sample_shading: false,
+            },
+            AntiAliasing::Msaa(_) => SamplePattern {
+                offsets: vec![(1, -3), (-1, 3), (5, 1), (-3, -5), (-5, 5), (-7, -1), (3, 7), (7, -7)],
+                per_sample_shading: false,
+            },
+            AntiAliasing::Ssaa(factor) => {
+                let factor = factor as i32;
+                // Centers of a factor x factor grid of equal cells
+                let cell = |i: i32| ((2 * i + 1 - factor) * SAMPLE_GRID) / (2 * factor);
+                let offsets = (0..factor)
+                    .flat_map(|y| (0..factor).map(move |x| (cell(x), cell(y))))
+                    .collect();
+                SamplePattern {
+                    offsets,
+                    per_sample_shading: true,
+                }
+            }
+        }
+    }
+}
+
+impl SamplePattern {
+    pub fn sample_count(&self) -> usize {
+        self.offsets.len()
+    }
+}
@@ -0,0 +1,109 @@
+// asteroids.rs
+#![allow(dead_code)]
+
+use crate::matrix::create_model_matrix;
+use crate::obj::Obj;
+use crate::pipeline::Instance;
+use crate::vertex::Vertex;
+use raylib::prelude::*;
+use std::f32::consts::TAU;
+
+// Between Namek's orbit (7) and Jupiter's (10.5)
+const INNER_RADIUS: f32 = 8.2;
+const OUTER_RADIUS: f32 = 9.6;
+
+/// A ring of rocks between Namek and Jupiter, all drawn from one small mesh
+pub struct AsteroidBelt {
+    pub mesh: Obj,
+    rocks: Vec<Rock>,
+}
+
+/// Where a rock sits on the ring and how it looks, fixed when the belt is made
+struct Rock {
+    radius: f32,
+    angle: f32,
+    height: f32,
+    scale: f32,
+    spin: Vector3,
+    params: Vector4,
+}
+
+// Small integer hash mapped to [0, 1), so the belt is the same on every run
+fn random(seed: u32, channel: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9E37_79B9) ^ channel.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Icosahedron with its corners pushed in and out a little, 12 vertices and 20 faces.
+/// Normals point away from the center, which shades it like a lumpy ball.
+pub fn rock_mesh() -> Obj {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let corners = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ];
+    let vertices = corners
+        .iter()
+        .enumerate()
+        .map(|(i, &(x, y, z))| {
+            let direction = Vector3::new(x, y, z).normalized();
+            let bump = 0.8 + 0.4 * random(i as u32, 7);
+            Vertex::new(direction * bump, direction, Vector2::zero())
+        })
+        .collect();
+
+    // Clockwise on screen once projected, like the faces of sphere.obj after Obj::load
+    let indices = vec![
+        0, 5, 11, 0, 1, 5, 0, 7, 1, 0, 10, 7, 0, 11, 10,
+        1, 9, 5, 5, 4, 11, 11, 2, 10, 10, 6, 7, 7, 8, 1,
+        3, 4, 9, 3, 2, 4, 3, 6, 2, 3, 8, 6, 3, 9, 8,
+        4, 5, 9, 2, 11, 4, 6, 10, 2, 8, 7, 6, 9, 1, 8,
+    ];
+
+    Obj { vertices, indices }
+}
+
+impl AsteroidBelt {
+    /// `count` rocks spread around the sun
+    pub fn new(count: u32) -> Self {
+        let rocks = (0..count)
+            .map(|i| Rock {
+                radius: INNER_RADIUS + (OUTER_RADIUS - INNER_RADIUS) * random(i, 0),
+                angle: TAU * random(i, 1),
+                height: (random(i, 2) - 0.5) * 0.4,
+                scale: 0.03 + 0.07 * random(i, 3).powi(2),
+                spin: Vector3::new(random(i, 4) * 2.0, random(i, 5) * 2.0, random(i, 6)),
+                params: Vector4::new(random(i, 8), random(i, 9), 0.0, 0.0),
+            })
+            .collect();
+
+        AsteroidBelt { mesh: rock_mesh(), rocks }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rocks.is_empty()
+    }
+
+    /// One instance per rock as the belt is at simulated `time`. Inner rocks orbit faster.
+    pub fn instances(&self, time: f32) -> Vec<Instance> {
+        self.rocks
+            .iter()
+            .map(|rock| {
+                let angle = rock.angle + time * 2.5 / rock.radius;
+                let translation = Vector3::new(rock.radius * angle.cos(), rock.height, rock.radius * angle.sin());
+                let model_matrix = create_model_matrix(translation, rock.scale, rock.spin * time);
+                Instance::new(model_matrix, "rock").with_params(rock.params)
+            })
+            .collect()
+    }
+}
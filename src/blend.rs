@@ -0,0 +1,125 @@
+// blend.rs
+#![allow(dead_code)]
+
+use raylib::prelude::*;
+
+/// How a fragment's color is combined with the color already in the framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Replace the stored color, alpha is ignored
+    Opaque,
+    /// Mix by the fragment's alpha, `src * a + dst * (1 - a)`
+    Alpha,
+    /// Add the fragment scaled by its alpha, for glows and light
+    Additive,
+    /// Multiply the stored color by the fragment, faded towards white by `1 - a`
+    Multiply,
+}
+
+impl BlendMode {
+    /// Blends `src` (RGB plus alpha in `w`) over `dst`
+    #[inline]
+    pub fn apply(self, dst: Vector3, src: Vector4) -> Vector3 {
+        let color = Vector3::new(src.x, src.y, src.z);
+        let alpha = src.w.clamp(0.0, 1.0);
+        match self {
+            BlendMode::Opaque => color,
+            BlendMode::Alpha => color * alpha + dst * (1.0 - alpha),
+            BlendMode::Additive => dst + color * alpha,
+            BlendMode::Multiply => dst * (color * alpha + Vector3::one() * (1.0 - alpha)),
+        }
+    }
+}
+
+/// Per-draw output state: the blend mode and whether the draw writes depth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlendState {
+    pub mode: BlendMode,
+    pub depth_write: bool,
+}
+
+impl BlendState {
+    /// What every draw did before blending existed
+    pub const OPAQUE: BlendState = BlendState { mode: BlendMode::Opaque, depth_write: true };
+
+    /// A see-through draw: depth tested against what is behind it, but without
+    /// writing depth so other transparent surfaces behind it still show
+    pub fn transparent(mode: BlendMode) -> Self {
+        BlendState { mode, depth_write: false }
+    }
+
+    /// Draws that have to wait until the opaque geometry is in and then go back to front
+    pub fn is_transparent(self) -> bool {
+        self.mode != BlendMode::Opaque
+    }
+}
+
+impl Default for BlendState {
+    fn default() -> Self {
+        BlendState::OPAQUE
+    }
+}
+
+/// How transparent draws are composited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    /// Transparent draws are sorted back to front as whole objects and blended
+    /// as they are rasterized. Cheap, but wrong where translucent surfaces intersect.
+    Sorted,
+    /// Every transparent fragment is kept in a per-pixel list and the lists are sorted
+    /// by depth before compositing, so the result doesn't depend on draw order.
+    /// Transparent draws never write depth in this mode.
+    OrderIndependent,
+}
+
+impl Transparency {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sorted" => Some(Transparency::Sorted),
+            "oit" | "order-independent" => Some(Transparency::OrderIndependent),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Transparency::Sorted => "sorted",
+            Transparency::OrderIndependent => "order-independent",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Transparency::Sorted => Transparency::OrderIndependent,
+            Transparency::OrderIndependent => Transparency::Sorted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vector3, expected: Vector3) {
+        assert!((actual - expected).length() < 1e-5, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn blend_modes_combine_the_source_with_the_destination() {
+        let dst = Vector3::new(0.2, 0.4, 0.8);
+        let src = Vector4::new(1.0, 0.5, 0.0, 0.25);
+        assert_close(BlendMode::Opaque.apply(dst, src), Vector3::new(1.0, 0.5, 0.0));
+        assert_close(BlendMode::Alpha.apply(dst, src), Vector3::new(0.4, 0.425, 0.6));
+        assert_close(BlendMode::Additive.apply(dst, src), Vector3::new(0.45, 0.525, 0.8));
+        assert_close(BlendMode::Multiply.apply(dst, src), Vector3::new(0.2, 0.35, 0.6));
+    }
+
+    #[test]
+    fn alpha_is_clamped_to_the_unit_range() {
+        let dst = Vector3::new(0.2, 0.4, 0.8);
+        let color = Vector3::new(1.0, 0.5, 0.0);
+        assert_close(BlendMode::Alpha.apply(dst, Vector4::new(1.0, 0.5, 0.0, 3.0)), color);
+        assert_close(BlendMode::Alpha.apply(dst, Vector4::new(1.0, 0.5, 0.0, -1.0)), dst);
+        assert_close(BlendMode::Additive.apply(dst, Vector4::new(1.0, 0.5, 0.0, 2.0)), dst + color);
+    }
+}
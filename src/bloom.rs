@@ -0,0 +1,237 @@
+// bloom.rs
+#![allow(dead_code)]
+
+use raylib::prelude::*;
+
+/// Glow around HDR colors brighter than `threshold`.
+/// The bright parts are blurred at several scales and the sum is added back
+/// before tone mapping. The default threshold sits above everything the planet
+/// shaders can output, so only the sun blooms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    /// Brightness (largest channel) above which a color starts to glow
+    pub threshold: f32,
+    /// Multiplier of the glow added back to the image
+    pub strength: f32,
+    /// Gaussian sigma in pixels of each pyramid level. The first level is a quarter
+    /// of the screen size and every next one halves it again, so the glow reaches
+    /// about `radius * 2^(levels + 1)` screen pixels
+    pub radius: f32,
+    /// Number of pyramid levels
+    pub levels: usize,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.5,
+            strength: 1.2,
+            radius: 1.5,
+            levels: 5,
+        }
+    }
+}
+
+/// An HDR image at one level of the pyramid
+struct Layer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3>,
+}
+
+impl Layer {
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> Vector3 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Half the size, each pixel the average of a 2x2 block
+    fn downsample(&self) -> Layer {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            let (y0, y1) = ((2 * y).min(self.height - 1), (2 * y + 1).min(self.height - 1));
+            for x in 0..width {
+                let (x0, x1) = ((2 * x).min(self.width - 1), (2 * x + 1).min(self.width - 1));
+                pixels.push((self.get(x0, y0) + self.get(x1, y0) + self.get(x0, y1) + self.get(x1, y1)) * 0.25);
+            }
+        }
+
+        Layer { width, height, pixels }
+    }
+
+    /// Bilinear resize to `width` x `height`
+    fn upsample(&self, width: usize, height: usize) -> Layer {
+        let columns = bilinear_taps(self.width, width);
+        let mut pixels = vec![Vector3::zero(); width * height];
+        let mut blended = vec![Vector3::zero(); self.width];
+
+        for (row, &taps) in pixels.chunks_exact_mut(width).zip(&bilinear_taps(self.height, height)) {
+            self.sample_row(taps, &columns, &mut blended, row);
+        }
+
+        Layer { width, height, pixels }
+    }
+
+    /// One bilinearly resampled row: the two source rows in `taps` are blended
+    /// into `blended`, which is then resampled horizontally into `out`
+    fn sample_row(&self, (y0, y1, ty): (usize, usize, f32), columns: &[(usize, usize, f32)], blended: &mut [Vector3], out: &mut [Vector3]) {
+        let top = &self.pixels[y0 * self.width..(y0 + 1) * self.width];
+        let bottom = &self.pixels[y1 * self.width..(y1 + 1) * self.width];
+        for ((pixel, &a), &b) in blended.iter_mut().zip(top).zip(bottom) {
+            *pixel = a * (1.0 - ty) + b * ty;
+        }
+        for (pixel, &(x0, x1, tx)) in out.iter_mut().zip(columns) {
+            *pixel = blended[x0] * (1.0 - tx) + blended[x1] * tx;
+        }
+    }
+
+    /// Separable Gaussian blur, edges clamped
+    fn blur(&self, kernel: &[f32]) -> Layer {
+        let half = kernel.len() / 2;
+        let mut horizontal = Vec::with_capacity(self.pixels.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = Vector3::zero();
+                for (i, &weight) in kernel.iter().enumerate() {
+                    let sx = (x + i).saturating_sub(half).min(self.width - 1);
+                    sum += self.get(sx, y) * weight;
+                }
+                horizontal.push(sum);
+            }
+        }
+
+        let mut pixels = Vec::with_capacity(self.pixels.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = Vector3::zero();
+                for (i, &weight) in kernel.iter().enumerate() {
+                    let sy = (y + i).saturating_sub(half).min(self.height - 1);
+                    sum += horizontal[sy * self.width + x] * weight;
+                }
+                pixels.push(sum);
+            }
+        }
+
+        Layer { width: self.width, height: self.height, pixels }
+    }
+}
+
+/// For every destination index, the two source indices and the weight of the second one
+fn bilinear_taps(source: usize, destination: usize) -> Vec<(usize, usize, f32)> {
+    let scale = source as f32 / destination as f32;
+    (0..destination)
+        .map(|i| {
+            let s = ((i as f32 + 0.5) * scale - 0.5).clamp(0.0, (source - 1) as f32);
+            let first = s.floor() as usize;
+            (first, (first + 1).min(source - 1), s.fract())
+        })
+        .collect()
+}
+
+/// Normalized 1D Gaussian covering three sigmas on each side
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let sigma = sigma.max(0.1);
+    let half = (sigma * 3.0).ceil() as i32;
+    let weights: Vec<f32> = (-half..=half)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+/// The blurred glow, kept at pyramid resolution and scaled up one row at a time
+pub struct Glow {
+    layer: Layer,
+    strength: f32,
+    rows: Vec<(usize, usize, f32)>,
+    columns: Vec<(usize, usize, f32)>,
+    blended: Vec<Vector3>,
+}
+
+impl Glow {
+    /// Writes the glow for screen row `y` into `out`, one value per pixel
+    pub fn row(&mut self, y: usize, out: &mut [Vector3]) {
+        self.layer.sample_row(self.rows[y], &self.columns, &mut self.blended, out);
+        for pixel in out.iter_mut() {
+            *pixel *= self.strength;
+        }
+    }
+}
+
+impl Bloom {
+    /// Only the part of a color above the threshold, keeping its hue
+    #[inline]
+    fn bright_part(&self, color: Vector3) -> Vector3 {
+        let brightness = color.x.max(color.y).max(color.z);
+        if brightness <= self.threshold {
+            Vector3::zero()
+        } else {
+            color * ((brightness - self.threshold) / brightness)
+        }
+    }
+
+    /// Glow of a `width` x `height` image stored as HDR samples,
+    /// `samples_per_pixel` of them next to each other for every pixel
+    pub fn compute(&self, samples: &[Vector3], samples_per_pixel: usize, width: usize, height: usize) -> Option<Glow> {
+        if self.strength <= 0.0 || self.levels == 0 || width == 0 || height == 0 {
+            return None;
+        }
+
+        // The bright part straight at a quarter of the resolution, each pixel
+        // the average of a 4x4 block; the glow is soft anyway and this keeps the pass cheap
+        const BLOCK: usize = 4;
+        let bright_width = width.div_ceil(BLOCK);
+        let bright_height = height.div_ceil(BLOCK);
+        let mut pixels = vec![Vector3::zero(); bright_width * bright_height];
+        for (y, row) in samples.chunks_exact(width * samples_per_pixel).enumerate() {
+            let bright_row = &mut pixels[(y / BLOCK) * bright_width..(y / BLOCK + 1) * bright_width];
+            for (i, &sample) in row.iter().enumerate() {
+                // Most of the screen is far below the threshold
+                if sample.x > self.threshold || sample.y > self.threshold || sample.z > self.threshold {
+                    bright_row[i / samples_per_pixel / BLOCK] += self.bright_part(sample);
+                }
+            }
+        }
+        // Blocks on the right and bottom edges may be cut short
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            let (bx, by) = (index % bright_width, index / bright_width);
+            let columns = BLOCK.min(width - bx * BLOCK);
+            let rows = BLOCK.min(height - by * BLOCK);
+            *pixel /= (columns * rows * samples_per_pixel) as f32;
+        }
+        let mut layer = Layer { width: bright_width, height: bright_height, pixels };
+
+        // Blur every level of the pyramid
+        let kernel = gaussian_kernel(self.radius);
+        let mut blurred = Vec::with_capacity(self.levels);
+        for _ in 0..self.levels {
+            blurred.push(layer.blur(&kernel));
+            if layer.width == 1 && layer.height == 1 {
+                break;
+            }
+            layer = layer.downsample();
+        }
+
+        // Add the levels back up, from the smallest to the largest
+        let level_count = blurred.len() as f32;
+        let mut glow = blurred.pop().expect("the pyramid has at least one level");
+        while let Some(mut larger) = blurred.pop() {
+            let upsampled = glow.upsample(larger.width, larger.height);
+            for (pixel, extra) in larger.pixels.iter_mut().zip(upsampled.pixels) {
+                *pixel += extra;
+            }
+            glow = larger;
+        }
+
+        Some(Glow {
+            rows: bilinear_taps(glow.height, height),
+            columns: bilinear_taps(glow.width, width),
+            blended: vec![Vector3::zero(); glow.width],
+            strength: self.strength / level_count,
+            layer: glow,
+        })
+    }
+}
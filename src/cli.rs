@@ -0,0 +1,293 @@
+// cli.rs
+use crate::antialiasing::AntiAliasing;
+use crate::blend::Transparency;
+use crate::bloom::Bloom;
+use crate::color::ColorSpace;
+use crate::golden::GoldenMode;
+use crate::image_io::{DepthFormat, ImageFormat};
+use crate::postprocess::STANDARD_PASSES;
+use crate::presenter::UpscaleFilter;
+use crate::tonemap::ToneMapping;
+use crate::viewport::ViewLayout;
+use crate::wireframe::PolygonMode;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+/// Command line options
+pub struct Options {
+    /// Render this many frames offscreen and exit, without opening a window
+    pub headless_frames: Option<u32>,
+    /// Render a single frame offscreen, save it here and exit
+    pub screenshot: Option<PathBuf>,
+    /// Also save the depth buffer of the `screenshot` frame here
+    pub depth_screenshot: Option<PathBuf>,
+    /// Simulated time of the frame written by `screenshot`
+    pub time: f32,
+    /// Save every rendered frame into this directory as a numbered sequence
+    pub record_dir: Option<PathBuf>,
+    pub record_format: ImageFormat,
+    pub anti_aliasing: AntiAliasing,
+    /// Scale applied to the HDR colors before tone mapping
+    pub exposure: f32,
+    pub tone_mapping: ToneMapping,
+    /// Space the shaders light in, `Srgb` keeps the original look
+    pub color_space: ColorSpace,
+    /// How overlapping transparent draws are composited
+    pub transparency: Transparency,
+    /// Glow around the brightest colors, `None` turns it off
+    pub bloom: Option<Bloom>,
+    /// Post-processing passes to start with turned on
+    pub post_passes: Vec<String>,
+    /// Polygon mode forced on every draw, `None` lets each draw pick
+    pub polygon_mode: Option<PolygonMode>,
+    /// Draw the triangle edges on top of the filled geometry
+    pub wireframe_overlay: bool,
+    /// Rocks in the asteroid belt, 0 leaves it out
+    pub asteroids: u32,
+    /// Draw the translucent haze around the Earth
+    pub atmosphere: bool,
+    /// Size of the framebuffer relative to the window, 0.5 renders a quarter of the pixels
+    pub render_scale: f32,
+    /// How the framebuffer is stretched to the window when the scale isn't 1
+    pub upscale_filter: UpscaleFilter,
+    /// Frame time in milliseconds the render scale adapts to, `None` keeps the scale fixed
+    pub target_frame_time: Option<f32>,
+    /// Bounds of the adaptive render scale
+    pub min_render_scale: f32,
+    pub max_render_scale: f32,
+    /// How the overview and the Earth close-up share the window
+    pub layout: ViewLayout,
+    /// Render the golden scenes and check them against (or overwrite) the references
+    pub golden: Option<GoldenMode>,
+    /// Largest per-channel difference a golden pixel may have
+    pub golden_tolerance: u8,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            headless_frames: None,
+            screenshot: None,
+            depth_screenshot: None,
+            time: 0.0,
+            record_dir: None,
+            record_format: ImageFormat::Png,
+            anti_aliasing: AntiAliasing::None,
+            exposure: 1.0,
+            tone_mapping: ToneMapping::Clamp,
+            color_space: ColorSpace::Linear,
+            transparency: Transparency::OrderIndependent,
+            bloom: Some(Bloom::default()),
+            post_passes: Vec::new(),
+            polygon_mode: None,
+            wireframe_overlay: false,
+            asteroids: 0,
+            atmosphere: false,
+            render_scale: 1.0,
+            upscale_filter: UpscaleFilter::Bilinear,
+            target_frame_time: None,
+            min_render_scale: 0.5,
+            max_render_scale: 1.0,
+            layout: ViewLayout::Single,
+            golden: None,
+            golden_tolerance: 2,
+        }
+    }
+}
+
+/// Limits of `--render-scale`, also kept by the [ and ] keys
+pub const MIN_RENDER_SCALE: f32 = 0.1;
+pub const MAX_RENDER_SCALE: f32 = 4.0;
+
+const USAGE: &str = "\
+Usage: shaders [OPTIONS]
+
+Options:
+  --headless <FRAMES>        Render FRAMES frames without opening a window
+  --screenshot <FILE>        Render one frame without a window and save it as FILE (.png or .ppm)
+  --depth <FILE>             With --screenshot, also save the depth buffer as FILE
+                             (.png as 16-bit grayscale, .raw or .f32 as little-endian floats)
+  --time <T>                 Simulated time of the --screenshot frame [default: 0]
+  --record <DIR>             Save every frame into DIR as frame_00000.png, frame_00001.png, ...
+  --record-format <FORMAT>   png or ppm [default: png]
+  --msaa <SAMPLES>           Multisample anti-aliasing with 1, 2, 4 or 8 samples [default: 1]
+  --ssaa <FACTOR>            Ordered-grid supersampling with FACTOR x FACTOR samples (1 to 4)
+  --tone-map <OPERATOR>      clamp, reinhard or aces [default: clamp]
+  --exposure <SCALE>         Multiplies the HDR colors before tone mapping [default: 1]
+  --color-space <SPACE>      Light in linear and encode the output to sRGB (linear),
+                             or light the sRGB palettes directly like before (srgb) [default: linear]
+  --transparency <MODE>      Composite transparent draws per pixel whatever their order (oit),
+                             or sort them back to front per draw (sorted) [default: oit]
+  --no-bloom                 Turn the bloom pass off, even with other bloom flags
+  --bloom-threshold <T>      HDR brightness where bloom starts [default: 1.5]
+  --bloom-strength <S>       How much glow is added back [default: 1.2]
+  --bloom-radius <PIXELS>    Blur radius of each pyramid level [default: 1.5]
+  --post <PASSES>            Comma-separated post-processing passes to turn on: fxaa,
+                             chromatic-aberration, color-grading, vignette, film-grain, gamma
+  --polygon-mode <MODE>      Draw every body as fill, wireframe or points
+  --wireframe                Draw the triangle edges on top of the filled bodies
+  --asteroids <COUNT>        Add a belt of COUNT instanced rocks between Namek and Jupiter
+  --atmosphere               Wrap the Earth in a translucent haze
+  --render-scale <SCALE>     Render at SCALE times the window size, from 0.1 to 4 [default: 1]
+  --upscale <FILTER>         Filter that stretches the frame to the window: nearest or bilinear
+                             [default: bilinear]
+  --target-frame-time <MS>   Adapt the render scale every few frames to hold MS milliseconds per frame
+  --min-render-scale <SCALE> Lowest scale --target-frame-time may pick [default: 0.5]
+  --max-render-scale <SCALE> Highest scale --target-frame-time may pick [default: 1]
+  --views <LAYOUT>           Show the overview alone (single), next to a close-up that follows
+                             Earth (side-by-side), or with the close-up inset (picture-in-picture)
+                             [default: single]
+  --golden-check             Render the golden scenes and compare them with assets/golden,
+                             writing renders and diffs of failures to target/golden
+  --golden-update            Render the golden scenes and overwrite assets/golden
+  --golden-tolerance <N>     Per-channel difference allowed by --golden-check [default: 2]
+  -h, --help                 Print this help";
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> T {
+    let value = value.unwrap_or_else(|| fail(&format!("{} expects a value", flag)));
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid value '{}' for {}", value, flag)))
+}
+
+fn parse_render_scale(flag: &str, value: Option<String>) -> f32 {
+    let scale: f32 = parse_value(flag, value);
+    if !(MIN_RENDER_SCALE..=MAX_RENDER_SCALE).contains(&scale) {
+        fail(&format!("{} takes a scale from 0.1 to 4, got {}", flag, scale));
+    }
+    scale
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        let mut options = Options::default();
+        let mut args = env::args().skip(1);
+        // Applied after every flag is read, so the bloom settings can't turn it back on
+        let mut no_bloom = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless_frames = Some(parse_value(&arg, args.next())),
+                "--screenshot" => options.screenshot = Some(parse_value(&arg, args.next())),
+                "--depth" => options.depth_screenshot = Some(parse_value(&arg, args.next())),
+                "--time" => options.time = parse_value(&arg, args.next()),
+                "--record" => options.record_dir = Some(parse_value(&arg, args.next())),
+                "--record-format" => {
+                    let format: String = parse_value(&arg, args.next());
+                    options.record_format = match format.as_str() {
+                        "png" => ImageFormat::Png,
+                        "ppm" => ImageFormat::Ppm,
+                        _ => fail(&format!("unknown image format '{}'", format)),
+                    };
+                }
+                "--msaa" => {
+                    let samples: u32 = parse_value(&arg, args.next());
+                    options.anti_aliasing = AntiAliasing::msaa(samples)
+                        .unwrap_or_else(|| fail(&format!("--msaa takes 1, 2, 4 or 8 samples, got {}", samples)));
+                }
+                "--ssaa" => {
+                    let factor: u32 = parse_value(&arg, args.next());
+                    options.anti_aliasing = AntiAliasing::ssaa(factor)
+                        .unwrap_or_else(|| fail(&format!("--ssaa takes a factor from 1 to 4, got {}", factor)));
+                }
+                "--tone-map" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.tone_mapping = ToneMapping::from_name(&name)
+                        .unwrap_or_else(|| fail(&format!("unknown tone mapping operator '{}'", name)));
+                }
+                "--color-space" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.color_space = ColorSpace::from_name(&name)
+                        .unwrap_or_else(|| fail(&format!("unknown color space '{}'", name)));
+                }
+                "--transparency" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.transparency = Transparency::from_name(&name)
+                        .unwrap_or_else(|| fail(&format!("unknown transparency mode '{}'", name)));
+                }
+                "--exposure" => options.exposure = parse_value(&arg, args.next()),
+                "--no-bloom" => no_bloom = true,
+                "--bloom-threshold" => options.bloom.get_or_insert_with(Bloom::default).threshold = parse_value(&arg, args.next()),
+                "--bloom-strength" => options.bloom.get_or_insert_with(Bloom::default).strength = parse_value(&arg, args.next()),
+                "--bloom-radius" => options.bloom.get_or_insert_with(Bloom::default).radius = parse_value(&arg, args.next()),
+                "--post" => {
+                    let list: String = parse_value(&arg, args.next());
+                    for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                        if !STANDARD_PASSES.contains(&name) {
+                            fail(&format!("unknown post-processing pass '{}'", name));
+                        }
+                        options.post_passes.push(name.to_string());
+                    }
+                }
+                "--polygon-mode" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.polygon_mode = Some(
+                        PolygonMode::from_name(&name).unwrap_or_else(|| fail(&format!("unknown polygon mode '{}'", name))),
+                    );
+                }
+                "--wireframe" => options.wireframe_overlay = true,
+                "--asteroids" => options.asteroids = parse_value(&arg, args.next()),
+                "--atmosphere" => options.atmosphere = true,
+                "--render-scale" => options.render_scale = parse_render_scale(&arg, args.next()),
+                "--target-frame-time" => {
+                    let ms: f32 = parse_value(&arg, args.next());
+                    if ms.is_nan() || ms <= 0.0 {
+                        fail(&format!("--target-frame-time takes a positive number of milliseconds, got {}", ms));
+                    }
+                    options.target_frame_time = Some(ms);
+                }
+                "--min-render-scale" => options.min_render_scale = parse_render_scale(&arg, args.next()),
+                "--max-render-scale" => options.max_render_scale = parse_render_scale(&arg, args.next()),
+                "--upscale" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.upscale_filter = UpscaleFilter::from_name(&name)
+                        .unwrap_or_else(|| fail(&format!("unknown upscale filter '{}'", name)));
+                }
+                "--views" => {
+                    let name: String = parse_value(&arg, args.next());
+                    options.layout = ViewLayout::from_name(&name)
+                        .unwrap_or_else(|| fail(&format!("unknown view layout '{}'", name)));
+                }
+                "--golden-check" => options.golden = Some(GoldenMode::Check),
+                "--golden-update" => options.golden = Some(GoldenMode::Update),
+                "--golden-tolerance" => options.golden_tolerance = parse_value(&arg, args.next()),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => fail(&format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if no_bloom {
+            options.bloom = None;
+        }
+
+        if let Some(path) = &options.screenshot
+            && ImageFormat::from_path(path).is_none()
+        {
+            fail(&format!("--screenshot needs a .png or .ppm file, got '{}'", path.display()));
+        }
+
+        if options.min_render_scale > options.max_render_scale {
+            fail("--min-render-scale can't be larger than --max-render-scale");
+        }
+
+        if let Some(path) = &options.depth_screenshot {
+            if options.screenshot.is_none() {
+                fail("--depth only works together with --screenshot");
+            }
+            if DepthFormat::from_path(path).is_none() {
+                fail(&format!("--depth needs a .png, .raw or .f32 file, got '{}'", path.display()));
+            }
+        }
+
+        options
+    }
+}
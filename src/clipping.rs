@@ -0,0 +1,175 @@
+// clipping.rs
+use crate::vertex::Vertex;
+use raylib::prelude::*;
+
+/// One of the six planes of the view volume in homogeneous clip space.
+/// A point is inside when `-w <= x, y, z <= w`.
+#[derive(Clone, Copy)]
+enum ClipPlane {
+    Near,
+    Far,
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+impl ClipPlane {
+    // Near goes first so everything after it only ever sees positive w
+    const ALL: [ClipPlane; 6] = [
+        ClipPlane::Near,
+        ClipPlane::Far,
+        ClipPlane::Left,
+        ClipPlane::Right,
+        ClipPlane::Bottom,
+        ClipPlane::Top,
+    ];
+
+    /// Signed distance to the plane, positive on the inside
+    fn distance(self, p: &Vector4) -> f32 {
+        match self {
+            ClipPlane::Near => p.z + p.w,
+            ClipPlane::Far => p.w - p.z,
+            ClipPlane::Left => p.x + p.w,
+            ClipPlane::Right => p.w - p.x,
+            ClipPlane::Bottom => p.y + p.w,
+            ClipPlane::Top => p.w - p.y,
+        }
+    }
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Bitmask with one bit set for every plane the point lies outside of
+fn outcode(p: &Vector4) -> u8 {
+    ClipPlane::ALL
+        .iter()
+        .filter(|plane| plane.distance(p) < 0.0)
+        .fold(0, |code, plane| code | plane.bit())
+}
+
+/// Sutherland-Hodgman against a single plane, new vertices get every attribute interpolated
+fn clip_polygon(input: &[Vertex], plane: ClipPlane, output: &mut Vec<Vertex>) {
+    output.clear();
+
+    for i in 0..input.len() {
+        let current = &input[i];
+        let next = &input[(i + 1) % input.len()];
+
+        let d_current = plane.distance(&current.clip_position);
+        let d_next = plane.distance(&next.clip_position);
+
+        if d_current >= 0.0 {
+            output.push(current.clone());
+        }
+
+        // The edge crosses the plane, emit the intersection
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            output.push(current.lerp(next, t));
+        }
+    }
+}
+
+/// Clips a triangle in homogeneous clip space against the view frustum.
+/// The result is triangulated as a fan and appended to `out`; nothing is
+/// appended when the triangle is completely outside.
+pub fn clip_triangle(tri: &[Vertex; 3], out: &mut Vec<[Vertex; 3]>) {
+    let codes = [
+        outcode(&tri[0].clip_position),
+        outcode(&tri[1].clip_position),
+        outcode(&tri[2].clip_position),
+    ];
+
+    // Trivially accepted: every vertex inside
+    if codes[0] | codes[1] | codes[2] == 0 {
+        out.push(tri.clone());
+        return;
+    }
+
+    // Trivially rejected: every vertex outside the same plane
+    if codes[0] & codes[1] & codes[2] != 0 {
+        return;
+    }
+
+    let mut polygon: Vec<Vertex> = tri.to_vec();
+    let mut scratch = Vec::with_capacity(9);
+
+    for plane in ClipPlane::ALL {
+        if (codes[0] | codes[1] | codes[2]) & plane.bit() == 0 {
+            continue;
+        }
+
+        clip_polygon(&polygon, plane, &mut scratch);
+        std::mem::swap(&mut polygon, &mut scratch);
+
+        if polygon.len() < 3 {
+            return;
+        }
+    }
+
+    for i in 1..polygon.len() - 1 {
+        out.push([
+            polygon[0].clone(),
+            polygon[i].clone(),
+            polygon[i + 1].clone(),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip_vertex(x: f32, y: f32, z: f32, w: f32) -> Vertex {
+        let mut vertex = Vertex::new(Vector3::new(x, y, z), Vector3::zero(), Vector2::zero());
+        vertex.clip_position = Vector4::new(x, y, z, w);
+        vertex
+    }
+
+    fn clip(tri: [Vertex; 3]) -> Vec<[Vertex; 3]> {
+        let mut out = Vec::new();
+        clip_triangle(&tri, &mut out);
+        out
+    }
+
+    fn is_inside(vertex: &Vertex) -> bool {
+        let p = vertex.clip_position;
+        ClipPlane::ALL.iter().all(|plane| plane.distance(&p) >= -1e-5)
+    }
+
+    #[test]
+    fn keeps_a_triangle_inside_the_frustum() {
+        let out = clip([clip_vertex(-0.5, -0.5, 0.0, 1.0), clip_vertex(0.5, -0.5, 0.0, 1.0), clip_vertex(0.0, 0.5, 0.0, 1.0)]);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0][1].clip_position, Vector4::new(0.5, -0.5, 0.0, 1.0));
+    }
+
+    #[test]
+    fn rejects_a_triangle_outside_one_plane() {
+        let out = clip([clip_vertex(2.0, 0.0, 0.0, 1.0), clip_vertex(3.0, 0.0, 0.0, 1.0), clip_vertex(2.5, 1.0, 0.0, 1.0)]);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn cuts_a_triangle_crossing_the_right_plane() {
+        // One corner sticks out past x = w, which leaves a quad: two triangles
+        let out = clip([clip_vertex(0.0, -0.5, 0.0, 1.0), clip_vertex(2.0, 0.0, 0.0, 1.0), clip_vertex(0.0, 0.5, 0.0, 1.0)]);
+        assert_eq!(out.len(), 2);
+        assert!(out.iter().flatten().all(is_inside));
+        // The new corners sit on the plane, with the position interpolated along
+        let on_plane: Vec<&Vertex> = out.iter().flatten().filter(|v| (v.clip_position.x - 1.0).abs() < 1e-5).collect();
+        assert!(!on_plane.is_empty());
+        assert!(on_plane.iter().all(|v| (v.position.x - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn cuts_a_triangle_behind_the_camera_at_the_near_plane() {
+        // Two corners behind the eye, with negative w
+        let out = clip([clip_vertex(0.0, 0.0, 0.5, 1.0), clip_vertex(0.5, 0.0, -2.0, -1.0), clip_vertex(-0.5, 0.0, -2.0, -1.0)]);
+        assert!(!out.is_empty());
+        assert!(out.iter().flatten().all(|v| v.clip_position.w > 0.0 && is_inside(v)));
+    }
+}
@@ -0,0 +1,88 @@
+// color.rs
+#![allow(dead_code)]
+
+use raylib::prelude::*;
+
+/// sRGB transfer function, encoded value to linear light
+#[inline]
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Inverse of `srgb_to_linear`, linear light to encoded value
+#[inline]
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Space the shaders light and blend colors in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Palettes are lit as they are written and go straight to the output,
+    /// what the renderer always did
+    Srgb,
+    /// Palettes are decoded to linear light, lit there, and encoded back to sRGB
+    /// when written to the 8-bit output
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "srgb" => Some(ColorSpace::Srgb),
+            "linear" => Some(ColorSpace::Linear),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            ColorSpace::Srgb => ColorSpace::Linear,
+            ColorSpace::Linear => ColorSpace::Srgb,
+        }
+    }
+
+    /// Turns a palette color written in sRGB into the working space
+    #[inline]
+    pub fn decode(self, color: Vector3) -> Vector3 {
+        match self {
+            ColorSpace::Srgb => color,
+            ColorSpace::Linear => Vector3::new(srgb_to_linear(color.x), srgb_to_linear(color.y), srgb_to_linear(color.z)),
+        }
+    }
+
+    /// Turns a working space color in [0, 1] into the value stored in the 8-bit output
+    #[inline]
+    pub fn encode(self, color: Vector3) -> Vector3 {
+        match self {
+            ColorSpace::Srgb => color,
+            ColorSpace::Linear => Vector3::new(linear_to_srgb(color.x), linear_to_srgb(color.y), linear_to_srgb(color.z)),
+        }
+    }
+
+    /// One channel of a working space color as the byte stored in the 8-bit output.
+    /// `Srgb` truncates like the renderer always did, `Linear` rounds so 8-bit colors
+    /// survive the decode and encode round trip.
+    #[inline]
+    pub fn encode_byte(self, c: f32) -> u8 {
+        match self {
+            ColorSpace::Srgb => (c.clamp(0.0, 1.0) * 255.0) as u8,
+            ColorSpace::Linear => (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0 + 0.5) as u8,
+        }
+    }
+}
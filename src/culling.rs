@@ -0,0 +1,131 @@
+// culling.rs
+#![allow(dead_code)]
+
+use crate::vertex::Vertex;
+
+/// Which side of a triangle gets discarded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+/// Winding order of front-facing triangles, as seen on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrontFace {
+    CounterClockwise,
+    Clockwise,
+}
+
+pub struct Culler {
+    pub mode: CullMode,
+    pub front_face: FrontFace,
+    pub culled: usize,
+}
+
+/// Twice the signed area of the triangle in screen space.
+/// Screen y grows downwards, so a positive area means clockwise on screen.
+fn signed_area(a: &Vertex, b: &Vertex, c: &Vertex) -> f32 {
+    let a = a.transformed_position;
+    let b = b.transformed_position;
+    let c = c.transformed_position;
+
+    (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y)
+}
+
+impl Culler {
+    pub fn new(mode: CullMode, front_face: FrontFace) -> Self {
+        Culler {
+            mode,
+            front_face,
+            culled: 0,
+        }
+    }
+
+    /// Returns true when the screen-space triangle should be skipped,
+    /// either because it faces the culled side or because it has no area.
+    pub fn cull(&mut self, a: &Vertex, b: &Vertex, c: &Vertex) -> bool {
+        let area = signed_area(a, b, c);
+
+        let culled = if area.abs() < 1e-10 {
+            true
+        } else {
+            let clockwise = area > 0.0;
+            let front_facing = match self.front_face {
+                FrontFace::Clockwise => clockwise,
+                FrontFace::CounterClockwise => !clockwise,
+            };
+
+            match self.mode {
+                CullMode::None => false,
+                CullMode::Back => !front_facing,
+                CullMode::Front => front_facing,
+            }
+        };
+
+        if culled {
+            self.culled += 1;
+        }
+
+        culled
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.culled = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raylib::prelude::*;
+
+    fn screen_vertex(x: f32, y: f32) -> Vertex {
+        let mut vertex = Vertex::new(Vector3::new(x, y, 0.0), Vector3::zero(), Vector2::zero());
+        vertex.set_transformed(Vector3::new(x, y, 0.0), Vector3::zero());
+        vertex
+    }
+
+    // Right, then down: clockwise on screen, where y grows downwards
+    fn clockwise() -> [Vertex; 3] {
+        [screen_vertex(0.0, 0.0), screen_vertex(10.0, 0.0), screen_vertex(0.0, 10.0)]
+    }
+
+    fn counter_clockwise() -> [Vertex; 3] {
+        [screen_vertex(0.0, 0.0), screen_vertex(0.0, 10.0), screen_vertex(10.0, 0.0)]
+    }
+
+    fn culls(culler: &mut Culler, [a, b, c]: [Vertex; 3]) -> bool {
+        culler.cull(&a, &b, &c)
+    }
+
+    #[test]
+    fn back_culling_keeps_the_front_winding() {
+        let mut culler = Culler::new(CullMode::Back, FrontFace::Clockwise);
+        assert!(!culls(&mut culler, clockwise()));
+        assert!(culls(&mut culler, counter_clockwise()));
+
+        let mut culler = Culler::new(CullMode::Back, FrontFace::CounterClockwise);
+        assert!(culls(&mut culler, clockwise()));
+        assert!(!culls(&mut culler, counter_clockwise()));
+        assert_eq!(culler.culled, 1);
+    }
+
+    #[test]
+    fn front_culling_and_no_culling() {
+        let mut culler = Culler::new(CullMode::Front, FrontFace::Clockwise);
+        assert!(culls(&mut culler, clockwise()));
+        assert!(!culls(&mut culler, counter_clockwise()));
+
+        let mut culler = Culler::new(CullMode::None, FrontFace::Clockwise);
+        assert!(!culls(&mut culler, clockwise()));
+        assert!(!culls(&mut culler, counter_clockwise()));
+    }
+
+    #[test]
+    fn degenerate_triangles_are_always_culled() {
+        let mut culler = Culler::new(CullMode::None, FrontFace::Clockwise);
+        assert!(culls(&mut culler, [screen_vertex(0.0, 0.0), screen_vertex(5.0, 5.0), screen_vertex(10.0, 10.0)]));
+    }
+}
@@ -1,12 +1,17 @@
 // fragment.rs
 #![allow(dead_code)]
 
-use raylib::math::{Vector2, Vector3};
+use raylib::math::{Vector2, Vector3, Vector4};
 
 pub struct Fragment {
     pub position: Vector2,
     pub color: Vector3,
     pub depth: f32,
+    pub normal: Vector3,
+    pub world_position: Vector3,
+    pub tex_coords: Vector2,
+    /// Per-instance shader parameters, not interpolated
+    pub params: Vector4,
 }
 
 impl Fragment {
@@ -15,6 +20,30 @@ impl Fragment {
             position: Vector2::new(x, y),
             color,
             depth,
+            normal: Vector3::zero(),
+            world_position: Vector3::zero(),
+            tex_coords: Vector2::zero(),
+            params: Vector4::new(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn new_with_varyings(
+        x: f32,
+        y: f32,
+        color: Vector3,
+        depth: f32,
+        normal: Vector3,
+        world_position: Vector3,
+        tex_coords: Vector2,
+    ) -> Self {
+        Fragment {
+            position: Vector2::new(x, y),
+            color,
+            depth,
+            normal,
+            world_position,
+            tex_coords,
+            params: Vector4::new(0.0, 0.0, 0.0, 0.0),
         }
     }
 }
\ No newline at end of file
@@ -1,19 +1,136 @@
 // framebuffer.rs
+use crate::antialiasing::{AntiAliasing, SamplePattern};
+use crate::blend::{BlendMode, BlendState, Transparency};
+use crate::bloom::Bloom;
+use crate::color::ColorSpace;
+use crate::image_io::{self, DepthFormat, ImageFormat};
+use crate::matrix::linearize_depth;
+use crate::postprocess::PostProcess;
+use crate::rect::Rect;
+use crate::tonemap::ToneMapping;
 use raylib::prelude::*;
+use std::io;
+use std::path::Path;
 
+/// Software render target: HDR float samples plus a float depth buffer, and the
+/// tightly packed RGBA8 `color_buffer` they end up in.
+/// Doesn't need a window, presenting it is up to the caller.
+///
+/// Drawing writes unclamped colors into the samples (several per pixel with
+/// anti-aliasing). `resolve` is the final pass: it applies exposure and tone
+/// mapping to every sample and averages them into `color_buffer`, adding the
+/// bloom glow first when bloom is on. The post-processing chain then runs on the
+/// tone mapped image before it is written out.
 pub struct Framebuffer {
     pub width: i32,
     pub height: i32,
-    pub color_buffer: Image,
+    pub color_buffer: Vec<u8>,
     background_color: Color,
     current_color: Color,
+    anti_aliasing: AntiAliasing,
+    pattern: SamplePattern,
+    exposure: f32,
+    tone_mapping: ToneMapping,
+    color_space: ColorSpace,
+    bloom: Option<Bloom>,
+    transparency: Transparency,
+    // Order-independent transparency: the transparent fragments of each row, composited by `resolve`
+    transparent_rows: Vec<Vec<TransparentFragment>>,
+    post_process: PostProcess,
+    // Tone mapped color per pixel in [0, 1], what post-processing works on
+    display_buffer: Vec<Vector3>,
+    // Linear HDR color per sample, the samples of a pixel stored next to each other
+    sample_buffer: Vec<Vector3>,
+    // One depth per sample, the samples of a pixel stored next to each other
     depth_buffer: Vec<f32>,
 }
 
+const BYTES_PER_PIXEL: usize = 4;
+
+/// A translucent fragment waiting in its pixel's list for order-independent compositing
+#[derive(Clone, Copy)]
+struct TransparentFragment {
+    // Sample index within the row, `x * samples_per_pixel + sample`
+    sample: u32,
+    depth: f32,
+    color: Vector4,
+    mode: BlendMode,
+}
+
+impl TransparentFragment {
+    // Orders fragments at exactly the same depth by their contents, so not even
+    // coplanar surfaces depend on the order they were drawn in
+    fn tie_break(&self, other: &Self) -> std::cmp::Ordering {
+        let key = |f: &Self| [f.color.w, f.color.x, f.color.y, f.color.z];
+        key(self)
+            .iter()
+            .zip(key(other).iter())
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|order| order.is_ne())
+            .unwrap_or((self.mode as u8).cmp(&(other.mode as u8)))
+    }
+}
+
+/// A horizontal band of rows borrowed from a framebuffer.
+/// Bands never overlap, so each one can be written from its own thread.
+pub struct FramebufferBand<'a> {
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub pattern: &'a SamplePattern,
+    /// Pixels outside this rectangle are never written
+    pub scissor: Rect,
+    transparency: Transparency,
+    color_buffer: &'a mut [Vector3],
+    depth_buffer: &'a mut [f32],
+    transparent_rows: &'a mut [Vec<TransparentFragment>],
+}
+
+/// When the depth test runs relative to the fragment shader
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthTestMode {
+    /// Test before shading so hidden fragments are never shaded
+    Early,
+    /// Shade first and test the depth the shader wrote, for shaders that change depth
+    Late,
+}
+
+// Depth test, blend and depth write shared by the whole framebuffer and its bands
+#[inline]
+fn write_fragment(color_slot: &mut Vector3, depth_slot: &mut f32, depth: f32, color: Vector4, blend: BlendState) {
+    if depth < *depth_slot {
+        if blend.depth_write {
+            *depth_slot = depth;
+        }
+        *color_slot = blend.mode.apply(*color_slot, color);
+    }
+}
+
+// Smallest and largest finite depth, or None when nothing was drawn
+fn depth_range(depths: impl Iterator<Item = f32>) -> Option<(f32, f32)> {
+    depths
+        .filter(|depth| depth.is_finite())
+        .fold(None, |range, depth| match range {
+            None => Some((depth, depth)),
+            Some((min, max)) => Some((min.min(depth), max.max(depth))),
+        })
+}
+
+#[inline]
+fn color_bytes(color: Color) -> [u8; BYTES_PER_PIXEL] {
+    [color.r, color.g, color.b, color.a]
+}
+
+#[inline]
+fn color_hdr(color: Color) -> Vector3 {
+    Vector3::new(color.r as f32 / 255.0, color.g as f32 / 255.0, color.b as f32 / 255.0)
+}
+
 impl Framebuffer {
     pub fn new(width: i32, height: i32) -> Self {
         let background_color = Color::BLACK; // Un color por defecto
-        let color_buffer = Image::gen_image_color(width, height, background_color);
+        let color_buffer = color_bytes(background_color).repeat((width * height) as usize);
+        let sample_buffer = vec![color_hdr(background_color); (width * height) as usize];
         let depth_buffer = vec![f32::INFINITY; (width * height) as usize];
         Framebuffer {
             width,
@@ -21,59 +138,449 @@ impl Framebuffer {
             color_buffer,
             background_color,
             current_color: Color::WHITE,
+            anti_aliasing: AntiAliasing::None,
+            pattern: AntiAliasing::None.pattern(),
+            exposure: 1.0,
+            tone_mapping: ToneMapping::Clamp,
+            color_space: ColorSpace::Srgb,
+            bloom: None,
+            transparency: Transparency::Sorted,
+            transparent_rows: vec![Vec::new(); height as usize],
+            post_process: PostProcess::new(),
+            display_buffer: vec![Vector3::zero(); (width * height) as usize],
+            sample_buffer,
             depth_buffer,
         }
     }
 
+    /// Changes the size of the render target, reallocating every buffer.
+    /// The contents are lost, so call it between frames.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        self.color_buffer = color_bytes(self.background_color).repeat((width * height) as usize);
+        self.display_buffer = vec![Vector3::zero(); (width * height) as usize];
+        self.transparent_rows = vec![Vec::new(); height as usize];
+        self.set_anti_aliasing(self.anti_aliasing);
+    }
+
+    /// Switches the sample layout, reallocating the sample buffers
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) {
+        self.anti_aliasing = anti_aliasing;
+        self.pattern = anti_aliasing.pattern();
+
+        let samples = (self.width * self.height) as usize * self.pattern.sample_count();
+        self.depth_buffer = vec![f32::INFINITY; samples];
+        self.sample_buffer = vec![self.color_space.decode(color_hdr(self.background_color)); samples];
+    }
+
+    /// Scale applied to the HDR colors before tone mapping
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    /// Space the colors are lit and blended in. `Linear` decodes the background and
+    /// encodes the output to sRGB, `Srgb` writes colors out untouched
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Turns the bloom pass on with the given settings, or off with `None`
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.bloom = bloom;
+    }
+
+    pub fn bloom(&self) -> Option<Bloom> {
+        self.bloom
+    }
+
+    /// How transparent draws are composited, see `Transparency`
+    pub fn set_transparency(&mut self, transparency: Transparency) {
+        self.transparency = transparency;
+    }
+
+    pub fn transparency(&self) -> Transparency {
+        self.transparency
+    }
+
+    /// Replaces the passes run at the end of `resolve`
+    pub fn set_post_process(&mut self, post_process: PostProcess) {
+        self.post_process = post_process;
+    }
+
+    /// For toggling passes at runtime
+    pub fn post_process_mut(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
     pub fn clear(&mut self) {
-        self.color_buffer.clear_background(self.background_color);
+        let background = color_bytes(self.background_color);
+        for pixel in self.color_buffer.chunks_exact_mut(BYTES_PER_PIXEL) {
+            pixel.copy_from_slice(&background);
+        }
+        self.sample_buffer.fill(self.color_space.decode(color_hdr(self.background_color)));
         self.depth_buffer.fill(f32::INFINITY);
+        for row in &mut self.transparent_rows {
+            row.clear();
+        }
     }
 
-    pub fn set_pixel(&mut self, x: i32, y: i32) {
-        if x >= 0 && x < self.width && y >= 0 && y < self.height {
-            self.color_buffer.draw_pixel(x, y, self.current_color);
+    /// Fills only the pixels inside `rect` with `color` and clears their depth,
+    /// for a view drawn over another one
+    pub fn clear_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersect(&Rect::from_size(self.width, self.height));
+        if rect.is_empty() {
+            return;
+        }
+
+        let samples = self.pattern.sample_count();
+        let fill = self.color_space.decode(color_hdr(color));
+        let (first, last) = ((rect.x0 as usize * samples) as u32, (rect.x1 as usize * samples) as u32);
+        for y in rect.y0..rect.y1 {
+            let row = (y * self.width) as usize;
+            let pixels = (row + rect.x0 as usize) * samples..(row + rect.x1 as usize) * samples;
+            self.sample_buffer[pixels.clone()].fill(fill);
+            self.depth_buffer[pixels].fill(f32::INFINITY);
+            self.transparent_rows[y as usize].retain(|fragment| fragment.sample < first || fragment.sample >= last);
         }
     }
-    
-    pub fn point(&mut self, x: i32, y: i32, depth: f32, color: Vector3) {
-        if x >= 0 && x < self.width && y >= 0 && y < self.height {
-            let index = (y * self.width + x) as usize;
 
-            if depth < self.depth_buffer[index] {
-                self.depth_buffer[index] = depth;
-                let pixel_color = Color::new(
-                    (color.x.clamp(0.0, 1.0) * 255.0) as u8,
-                    (color.y.clamp(0.0, 1.0) * 255.0) as u8,
-                    (color.z.clamp(0.0, 1.0) * 255.0) as u8,
-                    255,
-                );
-                self.color_buffer.draw_pixel(x, y, pixel_color);
+    /// Blends the stored transparent fragments into the samples, each sample's list from
+    /// the farthest fragment to the nearest, and empties the lists
+    fn composite_transparent(&mut self) {
+        let row_samples = self.width as usize * self.pattern.sample_count();
+        let rows = self
+            .transparent_rows
+            .iter_mut()
+            .zip(self.sample_buffer.chunks_exact_mut(row_samples))
+            .zip(self.depth_buffer.chunks_exact(row_samples));
+        for ((row, samples), depths) in rows {
+            if row.is_empty() {
+                continue;
+            }
+            row.sort_by(|a, b| a.sample.cmp(&b.sample).then(b.depth.total_cmp(&a.depth)).then_with(|| a.tie_break(b)));
+            for fragment in row.iter() {
+                let index = fragment.sample as usize;
+                // Opaque geometry drawn after the fragment was stored can still hide it
+                if fragment.depth < depths[index] {
+                    samples[index] = fragment.mode.apply(samples[index], fragment.color);
+                }
+            }
+            row.clear();
+        }
+    }
+
+    /// Final pass: adds the bloom glow, exposes and tone maps every sample, then
+    /// averages the samples of each pixel. The enabled post-processing passes run on
+    /// the result, which is then encoded for the color space and written to `color_buffer`. Call once the frame is drawn.
+    /// Tone mapping before averaging keeps edges against very bright surfaces smooth.
+    /// Transparent fragments kept for order-independent transparency are composited first.
+    pub fn resolve(&mut self) {
+        self.composite_transparent();
+
+        let samples = self.pattern.sample_count();
+        let (exposure, tone_mapping) = (self.exposure, self.tone_mapping);
+        let width = self.width as usize;
+        let mut glow = self
+            .bloom
+            .and_then(|bloom| bloom.compute(&self.sample_buffer, samples, width, self.height as usize));
+        let mut glow_row = vec![Vector3::zero(); width];
+
+        for (y, (pixels, row_samples)) in self
+            .display_buffer
+            .chunks_exact_mut(width)
+            .zip(self.sample_buffer.chunks_exact(width * samples))
+            .enumerate()
+        {
+            if let Some(glow) = &mut glow {
+                glow.row(y, &mut glow_row);
+            }
+
+            for ((pixel, pixel_samples), &extra) in pixels.iter_mut().zip(row_samples.chunks_exact(samples)).zip(&glow_row) {
+                let mut sum = Vector3::zero();
+                for &sample in pixel_samples {
+                    sum += tone_mapping.apply((sample + extra) * exposure);
+                }
+                *pixel = sum / samples as f32;
             }
         }
+
+        if self.post_process.is_active() {
+            let depths = self.pixel_depths();
+            self.post_process.run(&mut self.display_buffer, &depths, width, self.height as usize);
+        }
+
+        let color_space = self.color_space;
+        for (pixel, color) in self.color_buffer.chunks_exact_mut(BYTES_PER_PIXEL).zip(&self.display_buffer) {
+            pixel.copy_from_slice(&[
+                color_space.encode_byte(color.x),
+                color_space.encode_byte(color.y),
+                color_space.encode_byte(color.z),
+                255,
+            ]);
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32) {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            let samples = self.pattern.sample_count();
+            let first = (y * self.width + x) as usize * samples;
+            self.sample_buffer[first..first + samples].fill(self.color_space.decode(color_hdr(self.current_color)));
+        }
     }
-    
+
     pub fn get_pixel_color(&mut self, x: i32, y: i32) -> Option<Color> {
         if x >= 0 && x < self.width && y >= 0 && y < self.height {
-            Some(self.color_buffer.get_color(x, y))
+            let index = (y * self.width + x) as usize * BYTES_PER_PIXEL;
+            let pixel = &self.color_buffer[index..index + BYTES_PER_PIXEL];
+            Some(Color::new(pixel[0], pixel[1], pixel[2], pixel[3]))
         } else {
             None
         }
     }
 
+    /// Splits the sample and depth buffers into bands of `rows` rows each (the last one may be shorter).
+    /// The bands only write inside `scissor`.
+    pub fn bands_mut(&mut self, rows: i32, scissor: Rect) -> Vec<FramebufferBand<'_>> {
+        let width = self.width;
+        let samples = self.pattern.sample_count();
+        let chunk = (width * rows) as usize * samples;
+        let pattern = &self.pattern;
+        let transparency = self.transparency;
+
+        self.sample_buffer
+            .chunks_mut(chunk)
+            .zip(self.depth_buffer.chunks_mut(chunk))
+            .zip(self.transparent_rows.chunks_mut(rows as usize))
+            .enumerate()
+            .map(|(i, ((color_buffer, depth_buffer), transparent_rows))| FramebufferBand {
+                y: i as i32 * rows,
+                width,
+                height: (depth_buffer.len() / samples) as i32 / width,
+                pattern,
+                scissor,
+                transparency,
+                color_buffer,
+                depth_buffer,
+                transparent_rows,
+            })
+            .collect()
+    }
+
     pub fn set_background_color(&mut self, color: Color) {
         self.background_color = color;
     }
 
+    pub fn background_color(&self) -> Color {
+        self.background_color
+    }
+
     pub fn set_current_color(&mut self, color: Color) {
         self.current_color = color;
     }
 
-    pub fn swap_buffers(&self, d: &mut RaylibHandle, thread: &RaylibThread) {
-        if let Ok(texture) = d.load_texture_from_image(thread, &self.color_buffer) {
-            let mut d = d.begin_drawing(thread);
-            d.clear_background(self.background_color);
-            d.draw_texture(&texture, 0, 0, Color::WHITE);
+    /// Raw depths in screen space, `samples_per_pixel` of them per pixel; empty samples hold `f32::INFINITY`
+    pub fn depth_buffer(&self) -> &[f32] {
+        &self.depth_buffer
+    }
+
+    /// One depth per pixel: the nearest of its samples
+    pub fn pixel_depths(&self) -> Vec<f32> {
+        self.depth_buffer
+            .chunks_exact(self.pattern.sample_count())
+            .map(|samples| samples.iter().copied().fold(f32::INFINITY, f32::min))
+            .collect()
+    }
+
+    /// Depth buffer stretched over the full 16-bit range: the nearest drawn depth maps to 0,
+    /// the farthest one and empty pixels to 65535
+    pub fn depth_to_gray16(&self) -> Vec<u16> {
+        let depths = self.pixel_depths();
+        let (min, max) = depth_range(depths.iter().copied()).unwrap_or((0.0, 0.0));
+        let span = (max - min).max(f32::EPSILON);
+
+        depths
+            .iter()
+            .map(|&depth| {
+                if depth.is_finite() {
+                    (((depth - min) / span) * 65535.0).round() as u16
+                } else {
+                    u16::MAX
+                }
+            })
+            .collect()
+    }
+
+    /// Grayscale RGBA view of the linearized depth (distance from the camera), stretched
+    /// between the nearest and farthest drawn pixel. Near is black, far and empty are white.
+    /// Linear depth spreads the gray levels evenly, the raw buffer crowds them near 255.
+    pub fn linear_depth_rgba(&self, near: f32, far: f32) -> Vec<u8> {
+        let linear: Vec<f32> = self
+            .pixel_depths()
+            .iter()
+            .map(|&depth| if depth.is_finite() { linearize_depth(depth, near, far) } else { depth })
+            .collect();
+        let (min, max) = depth_range(linear.iter().copied()).unwrap_or((0.0, 0.0));
+        let span = (max - min).max(f32::EPSILON);
+
+        linear
+            .iter()
+            .flat_map(|&distance| {
+                let gray = if distance.is_finite() {
+                    (((distance - min) / span) * 255.0) as u8
+                } else {
+                    255
+                };
+                [gray, gray, gray, 255]
+            })
+            .collect()
+    }
+
+    /// Writes the depth buffer: `.png` as normalized 16-bit grayscale,
+    /// `.raw` or `.f32` as little-endian floats, `width * height` of them row by row.
+    /// With anti-aliasing each pixel gets the nearest of its sample depths.
+    pub fn save_depth(&self, path: &Path) -> io::Result<()> {
+        let format = DepthFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported depth extension: {}", path.display()))
+        })?;
+        image_io::save_with(path, |out| match format {
+            DepthFormat::Png16 => image_io::write_png_gray16(out, self.width as u32, self.height as u32, &self.depth_to_gray16()),
+            DepthFormat::RawF32 => image_io::write_raw_f32(out, &self.pixel_depths()),
+        })
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        image_io::save_rgba(path, ImageFormat::Png, self.width as u32, self.height as u32, &self.color_buffer)
+    }
+
+    pub fn save_ppm(&self, path: &Path) -> io::Result<()> {
+        image_io::save_rgba(path, ImageFormat::Ppm, self.width as u32, self.height as u32, &self.color_buffer)
+    }
+
+    /// Writes the color buffer, picking PNG or PPM from the file extension
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("unsupported image extension: {}", path.display()))
+        })?;
+        image_io::save_rgba(path, format, self.width as u32, self.height as u32, &self.color_buffer)
+    }
+}
+
+impl FramebufferBand<'_> {
+    /// Early depth test for one sample of a pixel, `x` and `y` are in framebuffer coordinates.
+    /// True when a fragment at `depth` would be closer than what is stored, so the rasterizer
+    /// can skip shading fragments that `point` would throw away anyway.
+    #[inline]
+    pub fn depth_test(&self, x: i32, y: i32, sample: usize, depth: f32) -> bool {
+        let local_y = y - self.y;
+        if x >= 0 && x < self.width && local_y >= 0 && local_y < self.height {
+            let index = (local_y * self.width + x) as usize * self.pattern.sample_count() + sample;
+            depth < self.depth_buffer[index]
+        } else {
+            false
+        }
+    }
+
+    /// Depth tests one sample of a pixel and blends `color` into it,
+    /// `x` and `y` are in framebuffer coordinates. With order-independent transparency
+    /// a blended fragment is stored for `resolve` instead.
+    pub fn point(&mut self, x: i32, y: i32, sample: usize, depth: f32, color: Vector4, blend: BlendState) {
+        let local_y = y - self.y;
+        if x >= 0 && x < self.width && local_y >= 0 && local_y < self.height && self.scissor.contains(x, y) {
+            let index = (local_y * self.width + x) as usize * self.pattern.sample_count() + sample;
+            if self.transparency == Transparency::OrderIndependent && blend.is_transparent() {
+                if depth < self.depth_buffer[index] {
+                    self.transparent_rows[local_y as usize].push(TransparentFragment {
+                        sample: (x as usize * self.pattern.sample_count() + sample) as u32,
+                        depth,
+                        color,
+                        mode: blend.mode,
+                    });
+                }
+                return;
+            }
+            write_fragment(&mut self.color_buffer[index], &mut self.depth_buffer[index], depth, color, blend);
+        }
+    }
+
+    /// Depth tests one sample of a pixel partly covered by an opaque line or point, and mixes
+    /// `color` into it by `coverage`. Only mostly covered samples write depth, so a line drawn
+    /// twice over the same pixels doesn't thicken.
+    pub fn coverage_point(&mut self, x: i32, y: i32, sample: usize, depth: f32, color: Vector3, coverage: f32) {
+        let local_y = y - self.y;
+        if x >= 0 && x < self.width && local_y >= 0 && local_y < self.height && self.scissor.contains(x, y) {
+            let index = (local_y * self.width + x) as usize * self.pattern.sample_count() + sample;
+            if depth < self.depth_buffer[index] {
+                if coverage >= 0.5 {
+                    self.depth_buffer[index] = depth;
+                }
+                self.color_buffer[index] = self.color_buffer[index].lerp(color, coverage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = 4;
+
+    // Draws the fragments into pixel (1, 2) in the given order, each as a separate draw
+    // with order-independent transparency, and returns the composited sample colors
+    fn composite(fragments: &[(f32, Vector4)]) -> Vec<Vector3> {
+        let mut framebuffer = Framebuffer::new(SIZE, SIZE);
+        framebuffer.set_transparency(Transparency::OrderIndependent);
+        framebuffer.clear();
+        for &(depth, color) in fragments {
+            for mut band in framebuffer.bands_mut(SIZE, Rect::from_size(SIZE, SIZE)) {
+                band.point(1, 2, 0, depth, color, BlendState::transparent(BlendMode::Alpha));
+            }
         }
-    } 
-}
\ No newline at end of file
+        framebuffer.composite_transparent();
+        assert!(framebuffer.transparent_rows.iter().all(Vec::is_empty));
+        framebuffer.sample_buffer.clone()
+    }
+
+    #[test]
+    fn overlapping_alpha_fragments_composite_the_same_in_any_order() {
+        let near = (0.25, Vector4::new(1.0, 0.0, 0.0, 0.5));
+        let far = (0.75, Vector4::new(0.0, 0.0, 1.0, 0.5));
+
+        let near_first = composite(&[near, far]);
+        let far_first = composite(&[far, near]);
+        assert_eq!(near_first, far_first);
+
+        // Far blended over the background, then near over that
+        let background = near_first[0];
+        let expected = BlendMode::Alpha.apply(BlendMode::Alpha.apply(background, far.1), near.1);
+        let pixel = near_first[(2 * SIZE + 1) as usize];
+        assert!((pixel - expected).length() < 1e-6, "{:?} != {:?}", pixel, expected);
+    }
+
+    #[test]
+    fn fragments_at_the_same_depth_composite_the_same_in_any_order() {
+        let red = (0.5, Vector4::new(1.0, 0.0, 0.0, 0.5));
+        let blue = (0.5, Vector4::new(0.0, 0.0, 1.0, 0.5));
+        assert_eq!(composite(&[red, blue]), composite(&[blue, red]));
+    }
+}
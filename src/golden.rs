@@ -0,0 +1,283 @@
+// golden.rs
+#![allow(dead_code)]
+
+use crate::antialiasing::AntiAliasing;
+use crate::asteroids::AsteroidBelt;
+use crate::blend::Transparency;
+use crate::bloom::Bloom;
+use crate::camera::Camera;
+use crate::color::ColorSpace;
+use crate::framebuffer::Framebuffer;
+use crate::image_io::{self, ImageFormat};
+use crate::light::Light;
+use crate::obj::Obj;
+use crate::pipeline::Pipeline;
+use crate::postprocess::PostProcess;
+use crate::tonemap::ToneMapping;
+use crate::viewport::ViewLayout;
+use crate::{Extras, layout_views, render_solar_system};
+use raylib::prelude::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where the checked-in reference images live
+pub const REFERENCE_DIR: &str = "assets/golden";
+/// Where renders and diff images of failing scenes are written
+pub const OUTPUT_DIR: &str = "target/golden";
+
+const WIDTH: i32 = 240;
+const HEIGHT: i32 = 160;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoldenMode {
+    /// Compare every scene against its reference
+    Check,
+    /// Overwrite the references with the current output
+    Update,
+}
+
+/// A fixed frame of the solar system: simulated time plus a camera, and the settings it renders with
+struct Scene {
+    name: &'static str,
+    time: f32,
+    eye: Vector3,
+    target: Vector3,
+    bloom: bool,
+    color_space: ColorSpace,
+    transparency: Transparency,
+    anti_aliasing: AntiAliasing,
+    tone_mapping: ToneMapping,
+    /// Post-processing passes turned on
+    post_passes: &'static [&'static str],
+    /// Triangle edges drawn on top of the bodies
+    wireframe: bool,
+    /// Rocks in the asteroid belt
+    asteroids: u32,
+    /// Translucent haze around the Earth
+    atmosphere: bool,
+    /// Cameras sharing the frame, the overview from `eye` plus the Earth close-up
+    layout: ViewLayout,
+}
+
+impl Scene {
+    /// The framebuffer's defaults: no bloom, sRGB, sorted transparency, no anti-aliasing,
+    /// clamped tone mapping and no post-processing. The builders below change one thing each.
+    fn base(name: &'static str, time: f32, eye: Vector3, target: Vector3) -> Self {
+        Scene {
+            name,
+            time,
+            eye,
+            target,
+            bloom: false,
+            color_space: ColorSpace::Srgb,
+            transparency: Transparency::Sorted,
+            anti_aliasing: AntiAliasing::None,
+            tone_mapping: ToneMapping::Clamp,
+            post_passes: &[],
+            wireframe: false,
+            asteroids: 0,
+            atmosphere: false,
+            layout: ViewLayout::Single,
+        }
+    }
+
+    /// The whole system from above, like the app starts
+    fn overview(name: &'static str) -> Self {
+        Scene::base(name, 0.8, Vector3::new(0.0, 8.0, 20.0), Vector3::new(0.0, 0.0, 0.0))
+    }
+
+    fn bloom(self) -> Self {
+        Scene { bloom: true, ..self }
+    }
+
+    fn color_space(self, color_space: ColorSpace) -> Self {
+        Scene { color_space, ..self }
+    }
+
+    fn transparency(self, transparency: Transparency) -> Self {
+        Scene { transparency, ..self }
+    }
+
+    fn anti_aliasing(self, anti_aliasing: AntiAliasing) -> Self {
+        Scene { anti_aliasing, ..self }
+    }
+
+    fn tone_mapping(self, tone_mapping: ToneMapping) -> Self {
+        Scene { tone_mapping, ..self }
+    }
+
+    fn post_passes(self, post_passes: &'static [&'static str]) -> Self {
+        Scene { post_passes, ..self }
+    }
+
+    fn wireframe(self) -> Self {
+        Scene { wireframe: true, ..self }
+    }
+
+    fn asteroids(self, asteroids: u32) -> Self {
+        Scene { asteroids, ..self }
+    }
+
+    fn atmosphere(self) -> Self {
+        Scene { atmosphere: true, ..self }
+    }
+
+    fn layout(self, layout: ViewLayout) -> Self {
+        Scene { layout, ..self }
+    }
+}
+
+// At time 0 every body sits on the positive x axis, which makes close-ups easy to aim
+fn scenes() -> Vec<Scene> {
+    let earth_eye = Vector3::new(4.0, 0.5, 2.5);
+    let earth = Vector3::new(4.0, 0.0, 0.0);
+    vec![
+        Scene::overview("system"),
+        Scene::overview("system_bloom").bloom(),
+        Scene::overview("system_linear").color_space(ColorSpace::Linear),
+        Scene::overview("system_aces").color_space(ColorSpace::Linear).bloom().tone_mapping(ToneMapping::Aces),
+        Scene::overview("system_post").post_passes(&["fxaa", "color-grading", "vignette"]),
+        Scene::overview("system_asteroids").asteroids(1000),
+        Scene::overview("system_side_by_side").layout(ViewLayout::SideBySide),
+        Scene::overview("system_picture_in_picture").layout(ViewLayout::PictureInPicture),
+        Scene::base("sun", 0.0, Vector3::new(-1.0, 1.0, 4.0), Vector3::new(0.0, 0.0, 0.0)),
+        Scene::base("earth", 0.0, earth_eye, earth),
+        Scene::base("earth_atmosphere", 0.0, earth_eye, earth).atmosphere(),
+        Scene::base("earth_oit", 0.0, earth_eye, earth).atmosphere().transparency(Transparency::OrderIndependent),
+        Scene::base("earth_msaa", 0.0, earth_eye, earth).anti_aliasing(AntiAliasing::Msaa(4)),
+        Scene::base("earth_wireframe", 0.0, Vector3::new(4.3, 0.4, 1.4), earth).wireframe(),
+        Scene::base("namek", 0.0, Vector3::new(7.0, 0.5, 2.5), Vector3::new(7.0, 0.0, 0.0)),
+        Scene::base("jupiter", 0.0, Vector3::new(10.5, 1.0, 4.5), Vector3::new(10.5, 0.0, 0.0)),
+    ]
+}
+
+/// Result of comparing a render against its reference
+struct Comparison {
+    /// Pixels where some channel differs by more than the tolerance
+    mismatched: usize,
+    /// Largest channel difference anywhere in the image
+    max_difference: u8,
+    /// Mismatched pixels in red over a darkened copy of the reference
+    diff_image: Vec<u8>,
+}
+
+fn compare(reference: &[u8], actual: &[u8], tolerance: u8) -> Comparison {
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    let mut diff_image = Vec::with_capacity(reference.len());
+
+    for (expected, got) in reference.chunks_exact(4).zip(actual.chunks_exact(4)) {
+        // Alpha is always opaque and PPM doesn't store it, so only RGB is compared
+        let difference = expected[..3]
+            .iter()
+            .zip(&got[..3])
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        if difference > tolerance {
+            mismatched += 1;
+            diff_image.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            diff_image.extend_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, 255]);
+        }
+    }
+
+    Comparison { mismatched, max_difference, diff_image }
+}
+
+fn save(path: &Path, rgba: &[u8]) -> io::Result<()> {
+    image_io::save_rgba(path, ImageFormat::Ppm, WIDTH as u32, HEIGHT as u32, rgba)
+}
+
+/// Renders every scene headlessly and checks or updates its reference image.
+/// Returns false when any scene fails the check.
+pub fn run(mode: GoldenMode, tolerance: u8, pipeline: &mut Pipeline, mesh: &Obj, light: &Light) -> bool {
+    let reference_dir = Path::new(REFERENCE_DIR);
+    let output_dir = Path::new(OUTPUT_DIR);
+
+    let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+    framebuffer.set_background_color(Color::new(5, 5, 15, 255));
+
+    if mode == GoldenMode::Check {
+        // Leftovers from an earlier run would look like fresh failures
+        let _ = fs::remove_dir_all(output_dir);
+    }
+
+    let mut failures = 0;
+
+    let scenes = scenes();
+    for scene in &scenes {
+        let camera = Camera::new(scene.eye, scene.target, Vector3::new(0.0, 1.0, 0.0));
+        framebuffer.set_bloom(scene.bloom.then(Bloom::default));
+        framebuffer.set_color_space(scene.color_space);
+        framebuffer.set_transparency(scene.transparency);
+        framebuffer.set_anti_aliasing(scene.anti_aliasing);
+        framebuffer.set_tone_mapping(scene.tone_mapping);
+        let mut post_process = PostProcess::standard();
+        for name in scene.post_passes {
+            post_process.set_enabled(name, true);
+        }
+        framebuffer.set_post_process(post_process);
+        pipeline.polygon_mode_override = None;
+        pipeline.wireframe_overlay = scene.wireframe;
+        let belt = (scene.asteroids > 0).then(|| AsteroidBelt::new(scene.asteroids));
+        let extras = Extras { belt: belt.as_ref(), atmosphere: scene.atmosphere };
+        let views = layout_views(scene.layout, &camera, scene.time, framebuffer.width, framebuffer.height);
+        render_solar_system(&mut framebuffer, pipeline, &views, mesh, extras, light, scene.time);
+
+        let reference_path = reference_dir.join(format!("{}.ppm", scene.name));
+
+        if mode == GoldenMode::Update {
+            fs::create_dir_all(reference_dir).expect("Failed to create the reference directory");
+            save(&reference_path, &framebuffer.color_buffer)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", reference_path.display(), err));
+            println!("updated {}", reference_path.display());
+            continue;
+        }
+
+        let failure = match image_io::read_ppm(&reference_path) {
+            Ok((width, height, _)) if width != WIDTH as u32 || height != HEIGHT as u32 => {
+                Some(format!("reference is {}x{}, expected {}x{}", width, height, WIDTH, HEIGHT))
+            }
+            Ok((_, _, reference)) => {
+                let comparison = compare(&reference, &framebuffer.color_buffer, tolerance);
+                if comparison.mismatched > 0 {
+                    fs::create_dir_all(output_dir).expect("Failed to create the golden output directory");
+                    let diff_path = output_dir.join(format!("{}.diff.ppm", scene.name));
+                    save(&diff_path, &comparison.diff_image)
+                        .unwrap_or_else(|err| panic!("Failed to write {}: {}", diff_path.display(), err));
+                    Some(format!(
+                        "{} pixels off by more than {} (max {}), diff in {}",
+                        comparison.mismatched,
+                        tolerance,
+                        comparison.max_difference,
+                        diff_path.display()
+                    ))
+                } else {
+                    None
+                }
+            }
+            Err(err) => Some(format!("can't read reference: {}", err)),
+        };
+
+        match failure {
+            None => println!("ok      {}", scene.name),
+            Some(reason) => {
+                failures += 1;
+                fs::create_dir_all(output_dir).expect("Failed to create the golden output directory");
+                let actual_path = output_dir.join(format!("{}.actual.ppm", scene.name));
+                save(&actual_path, &framebuffer.color_buffer)
+                    .unwrap_or_else(|err| panic!("Failed to write {}: {}", actual_path.display(), err));
+                println!("FAILED  {}: {}", scene.name, reason);
+            }
+        }
+    }
+
+    if mode == GoldenMode::Check {
+        println!("{} of {} golden scenes passed", scenes.len() - failures, scenes.len());
+    }
+    failures == 0
+}
@@ -0,0 +1,299 @@
+// image_io.rs
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+/// File formats the color buffer can be written as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    /// Picks the format from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+/// File formats the depth buffer can be written as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthFormat {
+    /// Normalized 16-bit grayscale PNG, near is black and far is white
+    Png16,
+    /// The stored `f32` depths as they are
+    RawF32,
+}
+
+impl DepthFormat {
+    /// Picks the format from the file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(DepthFormat::Png16),
+            "raw" | "f32" => Some(DepthFormat::RawF32),
+            _ => None,
+        }
+    }
+}
+
+/// Writes tightly packed RGBA8 pixels to `path` in the given format
+pub fn save_rgba(path: &Path, format: ImageFormat, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    save_with(path, |out| match format {
+        ImageFormat::Png => write_png(out, width, height, rgba),
+        ImageFormat::Ppm => write_ppm(out, width, height, rgba),
+    })
+}
+
+/// Binary PPM (P6). PPM has no alpha channel, so alpha is dropped.
+pub fn write_ppm<W: Write>(out: &mut W, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+
+    let rgb: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    out.write_all(&rgb)
+}
+
+/// Reads a binary PPM (P6) with 8-bit samples back as RGBA8, returning width, height and pixels
+pub fn read_ppm(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+
+    // Header: magic, width, height and maxval separated by whitespace, '#' starts a comment
+    let mut fields = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if pos < data.len() && data[pos] == b'#' {
+            while pos < data.len() && data[pos] != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("truncated header"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+    // Exactly one whitespace byte separates the header from the samples
+    pos += 1;
+
+    if fields[0] != "P6" {
+        return Err(invalid("not a binary PPM"));
+    }
+    let parse = |field: &str| field.parse::<u32>().map_err(|_| invalid("bad header value"));
+    let (width, height, maxval) = (parse(&fields[1])?, parse(&fields[2])?, parse(&fields[3])?);
+    if maxval != 255 {
+        return Err(invalid("only 8-bit samples are supported"));
+    }
+
+    let len = width as usize * height as usize * 3;
+    let rgb = data.get(pos..pos + len).ok_or_else(|| invalid("truncated pixel data"))?;
+    let rgba = rgb
+        .chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+        .collect();
+    Ok((width, height, rgba))
+}
+
+/// 8-bit RGBA PNG. The image data is stored in uncompressed deflate blocks,
+/// which any decoder reads and keeps us free of a compression dependency.
+pub fn write_png<W: Write>(out: &mut W, width: u32, height: u32, rgba: &[u8]) -> io::Result<()> {
+    // Color type 6 is RGBA
+    write_png_image(out, width, height, 8, 6, rgba)
+}
+
+/// 16-bit grayscale PNG
+pub fn write_png_gray16<W: Write>(out: &mut W, width: u32, height: u32, gray: &[u16]) -> io::Result<()> {
+    // PNG stores 16-bit samples big-endian
+    let bytes: Vec<u8> = gray.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+    // Color type 0 is grayscale
+    write_png_image(out, width, height, 16, 0, &bytes)
+}
+
+fn write_png_image<W: Write>(out: &mut W, width: u32, height: u32, bit_depth: u8, color_type: u8, data: &[u8]) -> io::Result<()> {
+    out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Default compression, filter and interlace
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    // Every scanline starts with its filter type, 0 means unfiltered
+    let stride = data.len() / height.max(1) as usize;
+    let mut scanlines = Vec::with_capacity((stride + 1) * height as usize);
+    for row in data.chunks_exact(stride.max(1)) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Raw little-endian `f32` samples, row by row from the top, with no header
+pub fn write_raw_f32<W: Write>(out: &mut W, samples: &[f32]) -> io::Result<()> {
+    let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    out.write_all(&bytes)
+}
+
+/// Opens `path` for writing and hands a buffered writer to `write`
+pub fn save_with<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+{
+    let mut out = BufWriter::new(File::create(path)?);
+    write(&mut out)?;
+    out.flush()
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32(crc32(0xffff_ffff, kind), data) ^ 0xffff_ffff;
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream made of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+
+    let mut stream = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    // CMF/FLG: deflate with a 32K window, no preset dictionary
+    stream.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(is_final as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest run that can't overflow before taking the modulo
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Undoes `zlib_stored`, checking the block headers and the checksum on the way
+    fn unzlib_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(&stream[..2], &[0x78, 0x01]);
+        let mut data = Vec::new();
+        let mut at = 2;
+        loop {
+            let is_final = stream[at] == 1;
+            let len = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+            let nlen = u16::from_le_bytes([stream[at + 3], stream[at + 4]]);
+            assert_eq!(len, !nlen);
+            data.extend_from_slice(&stream[at + 5..at + 5 + len as usize]);
+            at += 5 + len as usize;
+            if is_final {
+                break;
+            }
+        }
+        assert_eq!(&stream[at..], &adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn crc32_of_iend() {
+        // The CRC every PNG ends with
+        assert_eq!(crc32(0xffff_ffff, b"IEND") ^ 0xffff_ffff, 0xae42_6082);
+    }
+
+    #[test]
+    fn adler32_of_wikipedia() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(&[]), 1);
+    }
+
+    #[test]
+    fn zlib_splits_long_data_into_stored_blocks() {
+        let data: Vec<u8> = (0..0xffff * 2 + 10).map(|i| (i * 7 % 251) as u8).collect();
+        let stream = zlib_stored(&data);
+        // Three blocks with a five byte header each, plus the zlib header and checksum
+        assert_eq!(stream.len(), data.len() + 3 * 5 + 2 + 4);
+        assert_eq!(unzlib_stored(&stream), data);
+
+        assert_eq!(unzlib_stored(&zlib_stored(&[])), Vec::<u8>::new());
+        let exact = vec![42u8; 0xffff];
+        assert_eq!(unzlib_stored(&zlib_stored(&exact)), exact);
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let (width, height) = (3, 2);
+        let rgba: Vec<u8> = (0..width * height).flat_map(|i| [i as u8 * 40, 255 - i as u8, 7, 255]).collect();
+
+        let path = std::env::temp_dir().join(format!("image_io_round_trip_{}.ppm", std::process::id()));
+        save_rgba(&path, ImageFormat::Ppm, width, height, &rgba).expect("Failed to write the ppm");
+        let read = read_ppm(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(read.expect("Failed to read the ppm"), (width, height, rgba));
+    }
+}
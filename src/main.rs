@@ -7,78 +7,272 @@ mod vertex;
 mod camera;
 mod shaders;
 mod light;
+mod clipping;
+mod culling;
+mod rect;
+mod tiles;
+mod pipeline;
+mod presenter;
+mod cli;
+mod image_io;
+mod golden;
+mod antialiasing;
+mod tonemap;
+mod bloom;
+mod postprocess;
+mod color;
+mod blend;
+mod wireframe;
+mod asteroids;
+mod resolution;
+mod viewport;
 
-use triangle::triangle;
 use obj::Obj;
 use framebuffer::Framebuffer;
 use raylib::prelude::*;
-use std::thread;
-use std::time::Duration;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
 use std::f32::consts::PI;
-use matrix::{create_model_matrix, create_projection_matrix, create_viewport_matrix};
-use vertex::Vertex;
+use matrix::{create_model_matrix, create_projection_matrix, create_view_matrix, multiply_matrix_vector4};
 use camera::Camera;
-use shaders::{vertex_shader, fragment_shaders};
 use light::Light;
+use culling::{Culler, CullMode, FrontFace};
+use tiles::TiledRasterizer;
+use pipeline::{Instance, Pipeline};
+use presenter::{RaylibPresenter, UpscaleFilter};
+use cli::{Options, MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+use image_io::ImageFormat;
+use postprocess::PostProcess;
+use color::ColorSpace;
+use blend::{BlendMode, BlendState};
+use wireframe::PolygonMode;
+use asteroids::AsteroidBelt;
+use resolution::DynamicResolution;
+use rect::Rect;
+use viewport::{View, ViewLayout};
 
+const NEAR_PLANE: f32 = 0.1;
+const FAR_PLANE: f32 = 100.0;
+// Frame drawn around every view after the first
+const VIEW_BORDER: i32 = 2;
+const VIEW_BORDER_COLOR: Color = Color::new(90, 90, 110, 255);
+// Frame rate the window is held at while the render scale is fixed
+const TARGET_FPS: u32 = 60;
+// Simulated time per second in the window, the 0.005 per frame that headless renders step by, at 60 fps
+const SIMULATION_SPEED: f32 = 0.3;
+// Longest real time one window frame advances the simulation by, so a stall doesn't jump the orbits
+const MAX_FRAME_STEP: f32 = 0.1;
+// Frame time the V key adapts the render scale to when --target-frame-time isn't given, 60 fps
+const DEFAULT_TARGET_FRAME_TIME: f32 = 16.6;
+
+#[derive(Clone)]
 pub struct Uniforms {
     pub model_matrix: Matrix,
     pub view_matrix: Matrix,
     pub projection_matrix: Matrix,
     pub viewport_matrix: Matrix,
+    pub color_space: ColorSpace,
 }
 
-fn render(framebuffer: &mut Framebuffer, uniforms: &Uniforms, vertex_array: &[Vertex], light: &Light, shader_type: &str) {
-    // Vertex Shader Stage
-    let mut transformed_vertices = Vec::with_capacity(vertex_array.len());
-    for vertex in vertex_array {
-        let transformed = vertex_shader(vertex, uniforms);
-        transformed_vertices.push(transformed);
-    }
+/// Earth's translation, scale and rotation at simulated `time`, as `create_model_matrix` takes them
+fn earth_transform(time: f32) -> (Vector3, f32, Vector3) {
+    let earth_orbit_radius = 4.0;
+    let earth_orbit_speed = 1.0;
+    let earth_angle = time * earth_orbit_speed;
+    let earth_x = earth_orbit_radius * earth_angle.cos();
+    let earth_z = earth_orbit_radius * earth_angle.sin();
+    let earth_translation = Vector3::new(earth_x, 0.0, earth_z);
+    let earth_scale = 0.8;
+    let earth_rotation = Vector3::new(0.0, time * 2.0, 0.0); // Self rotation
+    (earth_translation, earth_scale, earth_rotation)
+}
+
+/// View matrix of the close-up that follows Earth at simulated `time`.
+/// It looks at Earth from the sun's side of its orbit, a little above it.
+fn earth_close_up(time: f32) -> Matrix {
+    let (translation, scale, rotation) = earth_transform(time);
+    // The model matrix rotates after translating, so the center is wherever it sends the origin
+    let center = multiply_matrix_vector4(&create_model_matrix(translation, scale, rotation), &Vector4::new(0.0, 0.0, 0.0, 1.0));
+    let earth = Vector3::new(center.x, center.y, center.z);
+    // Off to the side of the line to the sun, so the terminator shows next to the day side
+    let up = Vector3::new(0.0, 1.0, 0.0);
+    let to_sun = (-earth).normalized();
+    let side = up.cross(to_sun);
+    let eye = earth + (to_sun * 0.6 + side * 0.8) * 3.5 + up * 1.2;
+    create_view_matrix(eye, earth, up)
+}
+
+/// The views of `layout` on the framebuffer: the overview from `camera` first,
+/// then the close-up of Earth at simulated `time`
+fn layout_views(layout: ViewLayout, camera: &Camera, time: f32, width: i32, height: i32) -> Vec<View> {
+    let view_matrices = [camera.get_view_matrix(), earth_close_up(time)];
+    layout
+        .rects(width, height)
+        .into_iter()
+        .zip(view_matrices)
+        .map(|(rect, view_matrix)| View::new(rect, view_matrix))
+        .collect()
+}
+
+/// Optional parts of the scene, drawn on top of the sun, the planets and the moon
+#[derive(Clone, Copy, Default)]
+struct Extras<'a> {
+    /// Instanced rocks between Namek and Jupiter
+    belt: Option<&'a AsteroidBelt>,
+    /// Translucent haze around the Earth
+    atmosphere: bool,
+}
+
+/// Draws the sun, the planets, the moon and the extras as they are at simulated `time`,
+/// once for every view. Each view is scissored to its rectangle, and every view after the
+/// first is cleared and framed before it is drawn, so it can sit on top of the earlier ones.
+fn render_solar_system(
+    framebuffer: &mut Framebuffer,
+    pipeline: &mut Pipeline,
+    views: &[View],
+    mesh: &Obj,
+    extras: Extras,
+    light: &Light,
+    time: f32,
+) {
+    framebuffer.clear();
+    pipeline.begin_frame();
+    framebuffer.set_current_color(Color::new(200, 200, 255, 255));
+
+    let sun_scale = 1.5;
+    let sun_rotation = Vector3::new(0.0, time * 0.3, 0.0); // Slow rotation
+    let sun_translation = Vector3::new(0.0, 0.0, 0.0);
+    let sun_model_matrix = create_model_matrix(sun_translation, sun_scale, sun_rotation);
+
+    let (earth_translation, earth_scale, earth_rotation) = earth_transform(time);
+    let (earth_x, earth_z) = (earth_translation.x, earth_translation.z);
+    let earth_model_matrix = create_model_matrix(earth_translation, earth_scale, earth_rotation);
 
-    // Primitive Assembly Stage
-    let mut triangles = Vec::new();
-    for i in (0..transformed_vertices.len()).step_by(3) {
-        if i + 2 < transformed_vertices.len() {
-            triangles.push([
-                transformed_vertices[i].clone(),
-                transformed_vertices[i + 1].clone(),
-                transformed_vertices[i + 2].clone(),
-            ]);
+    let moon_orbit_radius = 1.5;
+    let moon_orbit_speed = 3.0;
+    let moon_angle = time * moon_orbit_speed;
+    let moon_x = earth_x + moon_orbit_radius * moon_angle.cos();
+    let moon_y = moon_orbit_radius * 0.3 * moon_angle.sin(); // Vertical component
+    let moon_z = earth_z + moon_orbit_radius * moon_angle.sin();
+    let moon_translation = Vector3::new(moon_x, moon_y, moon_z);
+    let moon_scale = 0.2;
+    let moon_rotation = Vector3::new(0.0, time * 1.0, 0.0);
+    let moon_model_matrix = create_model_matrix(moon_translation, moon_scale, moon_rotation);
+
+    let namek_orbit_radius = 7.0;
+    let namek_orbit_speed = 0.7;
+    let namek_angle = time * namek_orbit_speed;
+    let namek_x = namek_orbit_radius * namek_angle.cos();
+    let namek_z = namek_orbit_radius * namek_angle.sin();
+    let namek_translation = Vector3::new(namek_x, 0.0, namek_z);
+    let namek_scale = 0.75;
+    let namek_rotation = Vector3::new(0.0, time * 1.8, 0.0);
+    let namek_model_matrix = create_model_matrix(namek_translation, namek_scale, namek_rotation);
+
+    let jupiter_orbit_radius = 10.5;
+    let jupiter_orbit_speed = 0.4;
+    let jupiter_angle = time * jupiter_orbit_speed;
+    let jupiter_x = jupiter_orbit_radius * jupiter_angle.cos();
+    let jupiter_z = jupiter_orbit_radius * jupiter_angle.sin();
+    let jupiter_translation = Vector3::new(jupiter_x, 0.0, jupiter_z);
+    let jupiter_scale = 1.3; // Larger than other planets
+    let jupiter_rotation = Vector3::new(0.0, time * 3.0, 0.0); // Fast rotation for gas giant
+    let jupiter_model_matrix = create_model_matrix(jupiter_translation, jupiter_scale, jupiter_rotation);
+
+    let bodies = [
+        Instance::new(sun_model_matrix, "sun"),
+        Instance::new(earth_model_matrix, "earth"),
+        Instance::new(moon_model_matrix, "moon"),
+        Instance::new(namek_model_matrix, "namek"),
+        Instance::new(jupiter_model_matrix, "jupiter"),
+    ];
+    let rocks = extras.belt.map_or_else(Vec::new, |belt| belt.instances(time));
+    let atmosphere_model_matrix = create_model_matrix(earth_translation, earth_scale * 1.08, earth_rotation);
+
+    for (index, view) in views.iter().enumerate() {
+        if index > 0 {
+            let rect = view.rect;
+            let border = Rect::new(rect.x0 - VIEW_BORDER, rect.y0 - VIEW_BORDER, rect.x1 + VIEW_BORDER, rect.y1 + VIEW_BORDER);
+            framebuffer.clear_rect(border, VIEW_BORDER_COLOR);
+            framebuffer.clear_rect(rect, framebuffer.background_color());
         }
-    }
+        pipeline.scissor = Some(view.rect);
 
-    // Rasterization Stage
-    let mut fragments = Vec::new();
-    for tri in &triangles {
-        fragments.extend(triangle(&tri[0], &tri[1], &tri[2], light));
-    }
+        // Every body shares the view's camera, each instance brings its own model matrix
+        let uniforms = Uniforms {
+            model_matrix: Matrix::identity(),
+            view_matrix: view.view_matrix,
+            projection_matrix: create_projection_matrix(PI / 3.0, view.aspect_ratio(), NEAR_PLANE, FAR_PLANE),
+            viewport_matrix: view.viewport_matrix(),
+            color_space: framebuffer.color_space(),
+        };
+
+        pipeline.render_instanced(framebuffer, &uniforms, mesh, &bodies, light);
+
+        if let Some(belt) = extras.belt {
+            pipeline.render_instanced(framebuffer, &uniforms, &belt.mesh, &rocks, light);
+        }
+
+        // Translucent haze just above the surface, blended once the opaque bodies are in
+        if extras.atmosphere {
+            let atmosphere_uniforms = Uniforms {
+                model_matrix: atmosphere_model_matrix,
+                ..uniforms
+            };
+            pipeline.render_blended(
+                framebuffer,
+                &atmosphere_uniforms,
+                mesh,
+                light,
+                "atmosphere",
+                BlendState::transparent(BlendMode::Alpha),
+            );
+        }
 
-    // Fragment Processing Stage
-    for fragment in fragments {
-        let final_color = fragment_shaders(&fragment, uniforms, shader_type);
-            
-        framebuffer.point(
-            fragment.position.x as i32,
-            fragment.position.y as i32,
-            fragment.depth,
-            final_color,
-        );
+        // Sorted transparency has to finish inside the view's scissor, before the next view clears
+        pipeline.end_frame(framebuffer);
     }
+
+    pipeline.scissor = None;
+    framebuffer.resolve();
+}
+
+/// Saves one frame of a recording as `dir/frame_NNNNN.<ext>`
+fn record_frame(framebuffer: &Framebuffer, dir: &Path, format: ImageFormat, index: u32) {
+    let path = dir.join(format!("frame_{:05}.{}", index, format.extension()));
+    let result = match format {
+        ImageFormat::Png => framebuffer.save_png(&path),
+        ImageFormat::Ppm => framebuffer.save_ppm(&path),
+    };
+    result.unwrap_or_else(|err| panic!("Failed to write {}: {}", path.display(), err));
+}
+
+// Framebuffer size for a window of `width` x `height` drawn at `scale`, at least one pixel
+fn render_size(width: i32, height: i32, scale: f32) -> (i32, i32) {
+    (
+        ((width as f32 * scale).round() as i32).max(1),
+        ((height as f32 * scale).round() as i32).max(1),
+    )
 }
 
 fn main() {
+    let options = Options::from_args();
+
     let window_width = 1300;
     let window_height = 900;
+    let mut render_scale = options.render_scale;
+    let mut dynamic_resolution = options
+        .target_frame_time
+        .map(|ms| DynamicResolution::new(ms, options.min_render_scale, options.max_render_scale, render_scale));
+    if let Some(controller) = &dynamic_resolution {
+        render_scale = controller.scale();
+    }
 
-    let (mut window, raylib_thread) = raylib::init()
-        .size(window_width, window_height)
-        .title("Solar System - Earth, Jupiter, Namek & Sun")
-        .log_level(TraceLogLevel::LOG_WARNING)
-        .build();
+    let (width, height) = render_size(window_width, window_height, render_scale);
+    let mut framebuffer = Framebuffer::new(width, height);
 
-    let mut framebuffer = Framebuffer::new(window_width, window_height);
-    
     let mut camera = Camera::new(
         Vector3::new(0.0, 8.0, 20.0),
         Vector3::new(0.0, 0.0, 0.0),
@@ -88,107 +282,253 @@ fn main() {
     let light = Light::new(Vector3::new(0.0, 0.0, 0.0));
 
     let obj = Obj::load("assets/models/sphere.obj").expect("Failed to load obj");
-    let vertex_array = obj.get_vertex_array();
+    let belt = (options.asteroids > 0).then(|| AsteroidBelt::new(options.asteroids));
+    let extras = Extras { belt: belt.as_ref(), atmosphere: options.atmosphere };
 
     framebuffer.set_background_color(Color::new(5, 5, 15, 255));
+    framebuffer.set_anti_aliasing(options.anti_aliasing);
+    framebuffer.set_exposure(options.exposure);
+    framebuffer.set_tone_mapping(options.tone_mapping);
+    framebuffer.set_color_space(options.color_space);
+    framebuffer.set_transparency(options.transparency);
+    framebuffer.set_bloom(options.bloom);
+
+    let mut post_process = PostProcess::standard();
+    for name in &options.post_passes {
+        post_process.set_enabled(name, true);
+    }
+    framebuffer.set_post_process(post_process);
+
+    // Obj::load mirrors y, which turns the model's counter-clockwise faces clockwise on screen
+    let mut pipeline = Pipeline::new(
+        Culler::new(CullMode::Back, FrontFace::Clockwise),
+        TiledRasterizer::with_available_parallelism(64),
+    );
+    pipeline.polygon_mode_override = options.polygon_mode;
+    pipeline.wireframe_overlay = options.wireframe_overlay;
+    let mut layout = options.layout;
 
     let mut time: f32 = 0.0;
 
+    if let Some(mode) = options.golden {
+        let passed = golden::run(mode, options.golden_tolerance, &mut pipeline, &obj, &light);
+        process::exit(if passed { 0 } else { 1 });
+    }
+
+    if let Some(dir) = &options.record_dir {
+        fs::create_dir_all(dir).expect("Failed to create the recording directory");
+    }
+
+    if let Some(path) = &options.screenshot {
+        let views = layout_views(layout, &camera, options.time, framebuffer.width, framebuffer.height);
+        render_solar_system(&mut framebuffer, &mut pipeline, &views, &obj, extras, &light, options.time);
+        framebuffer
+            .save(path)
+            .unwrap_or_else(|err| panic!("Failed to write {}: {}", path.display(), err));
+        println!("Saved frame at time {} to {}", options.time, path.display());
+
+        if let Some(depth_path) = &options.depth_screenshot {
+            framebuffer
+                .save_depth(depth_path)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", depth_path.display(), err));
+            println!("Saved depth buffer to {}", depth_path.display());
+        }
+        return;
+    }
+
+    if let Some(frames) = options.headless_frames {
+        // Offscreen: no window and no raylib context, just the software framebuffer
+        let start = Instant::now();
+        let mut previous_frame_start = start;
+        for frame in 0..frames {
+            // The adaptive scale is fed the whole previous frame, recording included
+            let frame_start = Instant::now();
+            if let Some(controller) = &mut dynamic_resolution {
+                render_scale = controller.update(frame_start - previous_frame_start);
+            }
+            previous_frame_start = frame_start;
+
+            let (width, height) = render_size(window_width, window_height, render_scale);
+            framebuffer.resize(width, height);
+
+            time += 0.005;
+            let views = layout_views(layout, &camera, time, framebuffer.width, framebuffer.height);
+            render_solar_system(&mut framebuffer, &mut pipeline, &views, &obj, extras, &light, time);
+
+            if let Some(dir) = &options.record_dir {
+                record_frame(&framebuffer, dir, options.record_format, frame);
+            }
+        }
+        println!("Rendered {} frames headless in {:.2?}", frames, start.elapsed());
+        println!("Last frame: {} triangles culled", pipeline.culler.culled);
+        if let Some(controller) = &dynamic_resolution {
+            println!("Render scale settled at {:.2} ({:.1} ms per frame)", controller.scale(), controller.average_ms());
+        }
+        return;
+    }
+
+    let (mut window, raylib_thread) = raylib::init()
+        .size(window_width, window_height)
+        .resizable()
+        .title("Solar System - Earth, Jupiter, Namek & Sun")
+        .log_level(TraceLogLevel::LOG_WARNING)
+        .build();
+
+    let mut presenter =
+        RaylibPresenter::new(&mut window, &raylib_thread, framebuffer.width, framebuffer.height, options.upscale_filter)
+        .unwrap_or_else(|err| panic!("Failed to create the window texture: {}", err));
+    let mut frame: u32 = 0;
+    // Z swaps the color output for the linearized depth buffer
+    let mut show_depth = false;
+    // Whether the last frame failed to present, so an ongoing error is logged once
+    let mut present_failing = false;
+    let mut previous_frame_start = Instant::now();
+
     while !window.window_should_close() {
+        // The adaptive scale is fed the whole previous frame: input, rendering and presenting
+        let frame_start = Instant::now();
+        let frame_time = frame_start - previous_frame_start;
+        if let Some(controller) = &mut dynamic_resolution {
+            render_scale = controller.update(frame_time);
+        }
+        previous_frame_start = frame_start;
+
         camera.process_input(&window);
-        
-        framebuffer.clear();
-        framebuffer.set_current_color(Color::new(200, 200, 255, 255));
-
-        let view_matrix = camera.get_view_matrix();
-        let projection_matrix = create_projection_matrix(PI / 3.0, window_width as f32 / window_height as f32, 0.1, 100.0);
-        let viewport_matrix = create_viewport_matrix(0.0, 0.0, window_width as f32, window_height as f32);
-
-        time += 0.005;
-
-        let sun_scale = 1.5;
-        let sun_rotation = Vector3::new(0.0, time * 0.3, 0.0); // Slow rotation
-        let sun_translation = Vector3::new(0.0, 0.0, 0.0);
-        let sun_model_matrix = create_model_matrix(sun_translation, sun_scale, sun_rotation);
-        let sun_uniforms = Uniforms {
-            model_matrix: sun_model_matrix,
-            view_matrix: view_matrix.clone(),
-            projection_matrix: projection_matrix.clone(),
-            viewport_matrix: viewport_matrix.clone(),
-        };
-        render(&mut framebuffer, &sun_uniforms, &vertex_array, &light, "sun");
-
-        let earth_orbit_radius = 4.0;
-        let earth_orbit_speed = 1.0;
-        let earth_angle = time * earth_orbit_speed;
-        let earth_x = earth_orbit_radius * earth_angle.cos();
-        let earth_z = earth_orbit_radius * earth_angle.sin();
-        let earth_translation = Vector3::new(earth_x, 0.0, earth_z);
-        let earth_scale = 0.8;
-        let earth_rotation = Vector3::new(0.0, time * 2.0, 0.0); // Self rotation
-        let earth_model_matrix = create_model_matrix(earth_translation, earth_scale, earth_rotation);
-        let earth_uniforms = Uniforms {
-            model_matrix: earth_model_matrix,
-            view_matrix: view_matrix.clone(),
-            projection_matrix: projection_matrix.clone(),
-            viewport_matrix: viewport_matrix.clone(),
-        };
-        render(&mut framebuffer, &earth_uniforms, &vertex_array, &light, "earth");
-
-        let moon_orbit_radius = 1.5;
-        let moon_orbit_speed = 3.0;
-        let moon_angle = time * moon_orbit_speed;
-        let moon_x = earth_x + moon_orbit_radius * moon_angle.cos();
-        let moon_y = moon_orbit_radius * 0.3 * moon_angle.sin(); // Vertical component
-        let moon_z = earth_z + moon_orbit_radius * moon_angle.sin();
-        let moon_translation = Vector3::new(moon_x, moon_y, moon_z);
-        let moon_scale = 0.2;
-        let moon_rotation = Vector3::new(0.0, time * 1.0, 0.0);
-        let moon_model_matrix = create_model_matrix(moon_translation, moon_scale, moon_rotation);
-        let moon_uniforms = Uniforms {
-            model_matrix: moon_model_matrix,
-            view_matrix: view_matrix.clone(),
-            projection_matrix: projection_matrix.clone(),
-            viewport_matrix: viewport_matrix.clone(),
-        };
-        render(&mut framebuffer, &moon_uniforms, &vertex_array, &light, "moon");
-
-        let namek_orbit_radius = 7.0;
-        let namek_orbit_speed = 0.7;
-        let namek_angle = time * namek_orbit_speed;
-        let namek_x = namek_orbit_radius * namek_angle.cos();
-        let namek_z = namek_orbit_radius * namek_angle.sin();
-        let namek_translation = Vector3::new(namek_x, 0.0, namek_z);
-        let namek_scale = 0.75;
-        let namek_rotation = Vector3::new(0.0, time * 1.8, 0.0);
-        let namek_model_matrix = create_model_matrix(namek_translation, namek_scale, namek_rotation);
-        let namek_uniforms = Uniforms {
-            model_matrix: namek_model_matrix,
-            view_matrix: view_matrix.clone(),
-            projection_matrix: projection_matrix.clone(),
-            viewport_matrix: viewport_matrix.clone(),
-        };
-        render(&mut framebuffer, &namek_uniforms, &vertex_array, &light, "namek");
-
-        let jupiter_orbit_radius = 10.5;
-        let jupiter_orbit_speed = 0.4;
-        let jupiter_angle = time * jupiter_orbit_speed;
-        let jupiter_x = jupiter_orbit_radius * jupiter_angle.cos();
-        let jupiter_z = jupiter_orbit_radius * jupiter_angle.sin();
-        let jupiter_translation = Vector3::new(jupiter_x, 0.0, jupiter_z);
-        let jupiter_scale = 1.3; // Larger than other planets
-        let jupiter_rotation = Vector3::new(0.0, time * 3.0, 0.0); // Fast rotation for gas giant
-        let jupiter_model_matrix = create_model_matrix(jupiter_translation, jupiter_scale, jupiter_rotation);
-        let jupiter_uniforms = Uniforms {
-            model_matrix: jupiter_model_matrix,
-            view_matrix: view_matrix.clone(),
-            projection_matrix: projection_matrix.clone(),
-            viewport_matrix: viewport_matrix.clone(),
-        };
-        render(&mut framebuffer, &jupiter_uniforms, &vertex_array, &light, "jupiter");
 
-        framebuffer.swap_buffers(&mut window, &raylib_thread);
-        
-        thread::sleep(Duration::from_millis(16));
+        if window.is_key_pressed(KeyboardKey::KEY_Z) {
+            show_depth = !show_depth;
+        }
+        // B toggles bloom, with the settings from the command line
+        if window.is_key_pressed(KeyboardKey::KEY_B) {
+            let bloom = if framebuffer.bloom().is_some() { None } else { Some(options.bloom.unwrap_or_default()) };
+            framebuffer.set_bloom(bloom);
+            println!("Bloom: {}", if bloom.is_some() { "on" } else { "off" });
+        }
+        // T cycles the tone mapping operator, - and = change the exposure
+        if window.is_key_pressed(KeyboardKey::KEY_T) {
+            framebuffer.set_tone_mapping(framebuffer.tone_mapping().next());
+            println!("Tone mapping: {}", framebuffer.tone_mapping().name());
+        }
+        // L switches between linear lighting and the old sRGB look
+        if window.is_key_pressed(KeyboardKey::KEY_L) {
+            framebuffer.set_color_space(framebuffer.color_space().toggled());
+            println!("Color space: {}", framebuffer.color_space().name());
+        }
+        // P cycles the polygon mode forced on every draw, G toggles the wireframe overlay
+        if window.is_key_pressed(KeyboardKey::KEY_P) {
+            pipeline.polygon_mode_override = match pipeline.polygon_mode_override {
+                None => Some(PolygonMode::Wireframe),
+                Some(PolygonMode::Wireframe) => Some(PolygonMode::Points),
+                Some(_) => None,
+            };
+            println!("Polygon mode: {}", pipeline.polygon_mode_override.map_or("per draw", PolygonMode::name));
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_G) {
+            pipeline.wireframe_overlay = !pipeline.wireframe_overlay;
+            println!("Wireframe overlay: {}", if pipeline.wireframe_overlay { "on" } else { "off" });
+        }
+        // Tab cycles the view layout: overview only, side by side with the Earth close-up, or picture-in-picture
+        if window.is_key_pressed(KeyboardKey::KEY_TAB) {
+            layout = layout.next();
+            println!("Views: {}", layout.name());
+        }
+        // O switches between order-independent and sorted transparency
+        if window.is_key_pressed(KeyboardKey::KEY_O) {
+            framebuffer.set_transparency(framebuffer.transparency().toggled());
+            println!("Transparency: {}", framebuffer.transparency().name());
+        }
+        // [ and ] change the render scale by hand, which turns the adaptive scale off.
+        // V turns the adaptive scale on and off, U switches the upscaling filter
+        let scale_keys = [(KeyboardKey::KEY_LEFT_BRACKET, 1.0 / 1.25), (KeyboardKey::KEY_RIGHT_BRACKET, 1.25)];
+        for (key, factor) in scale_keys {
+            if window.is_key_pressed(key) {
+                render_scale = (render_scale * factor).clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+                dynamic_resolution = None;
+                println!("Render scale: {:.2}", render_scale);
+            }
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_V) {
+            dynamic_resolution = match dynamic_resolution {
+                Some(_) => None,
+                None => {
+                    let target_ms = options.target_frame_time.unwrap_or(DEFAULT_TARGET_FRAME_TIME);
+                    Some(DynamicResolution::new(target_ms, options.min_render_scale, options.max_render_scale, render_scale))
+                }
+            };
+            match &dynamic_resolution {
+                Some(controller) => println!("Adaptive render scale: on, {:.1} ms per frame", controller.target_ms),
+                None => println!("Adaptive render scale: off, {:.2}", render_scale),
+            }
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_U) {
+            let filter = match presenter.filter() {
+                UpscaleFilter::Nearest => UpscaleFilter::Bilinear,
+                UpscaleFilter::Bilinear => UpscaleFilter::Nearest,
+            };
+            presenter.set_filter(&raylib_thread, filter);
+            println!("Upscale filter: {}", filter.name());
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            framebuffer.set_exposure(framebuffer.exposure() / 1.25);
+            println!("Exposure: {:.2}", framebuffer.exposure());
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            framebuffer.set_exposure(framebuffer.exposure() * 1.25);
+            println!("Exposure: {:.2}", framebuffer.exposure());
+        }
+
+        // 1 to 6 toggle the post-processing passes, in the order they run
+        let pass_keys = [
+            KeyboardKey::KEY_ONE,
+            KeyboardKey::KEY_TWO,
+            KeyboardKey::KEY_THREE,
+            KeyboardKey::KEY_FOUR,
+            KeyboardKey::KEY_FIVE,
+            KeyboardKey::KEY_SIX,
+        ];
+        for (index, key) in pass_keys.into_iter().enumerate() {
+            if window.is_key_pressed(key)
+                && let Some((name, enabled)) = framebuffer.post_process_mut().toggle(index)
+            {
+                println!("{}: {}", name, if enabled { "on" } else { "off" });
+            }
+        }
+
+        // Waiting for a frame rate cap would count as frame time, so the adaptive scale runs uncapped
+        window.set_target_fps(if dynamic_resolution.is_some() { 0 } else { TARGET_FPS });
+
+        // Follow the window size; the projection and viewport are rebuilt from the framebuffer every frame
+        let (width, height) = render_size(window.get_screen_width(), window.get_screen_height(), render_scale);
+        framebuffer.resize(width, height);
+
+        // The orbits follow the clock, so they keep their speed at any frame rate
+        time += frame_time.as_secs_f32().min(MAX_FRAME_STEP) * SIMULATION_SPEED;
+        let views = layout_views(layout, &camera, time, framebuffer.width, framebuffer.height);
+        render_solar_system(&mut framebuffer, &mut pipeline, &views, &obj, extras, &light, time);
+
+        if let Some(dir) = &options.record_dir {
+            record_frame(&framebuffer, dir, options.record_format, frame);
+        }
+        frame += 1;
+
+        let presented = if show_depth {
+            let depth_view = framebuffer.linear_depth_rgba(NEAR_PLANE, FAR_PLANE);
+            presenter.present_rgba(&depth_view, framebuffer.width, framebuffer.height, &mut window, &raylib_thread)
+        } else {
+            presenter.present(&framebuffer, &mut window, &raylib_thread)
+        };
+        // A failed upload isn't worth closing the window over: the presenter keeps showing
+        // the last good frame and the next one tries again. Only the change is logged.
+        match presented {
+            Ok(()) if present_failing => {
+                present_failing = false;
+                eprintln!("Presenting frames again");
+            }
+            Err(err) if !present_failing => {
+                present_failing = true;
+                eprintln!("Failed to present the frame, showing the last one until it works: {}", err);
+            }
+            _ => {}
+        }
     }
 }
@@ -161,6 +161,9 @@ pub fn create_projection_matrix(fov_y: f32, aspect: f32, near: f32, far: f32) ->
     )
 }
 
+/// Scale the viewport matrix applies to NDC z, so stored depths run from -255 to 255
+pub const VIEWPORT_DEPTH_SCALE: f32 = 255.0;
+
 /// Creates a viewport matrix to transform NDC coordinates to screen space
 /// x, y: Viewport position (typically 0, 0)
 /// width, height: Viewport dimensions in pixels
@@ -171,7 +174,14 @@ pub fn create_viewport_matrix(x: f32, y: f32, width: f32, height: f32) -> Matrix
     new_matrix4(
         half_width, 0.0, 0.0, x + half_width,
         0.0, -half_height, 0.0, y + half_height,
-        0.0, 0.0, 255.0, 0.0,
+        0.0, 0.0, VIEWPORT_DEPTH_SCALE, 0.0,
         0.0, 0.0, 0.0, 1.0,
     )
-}
\ No newline at end of file
+}
+
+/// Turns a depth-buffer value back into the view-space distance from the camera,
+/// undoing the viewport depth scale and the perspective projection
+pub fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
+    let ndc_z = depth / VIEWPORT_DEPTH_SCALE;
+    (2.0 * far * near) / ((far + near) - ndc_z * (far - near))
+}
@@ -49,12 +49,4 @@ impl Obj {
 
         Ok(Obj { vertices, indices })
     }
-
-    pub fn get_vertex_array(&self) -> Vec<Vertex> {
-        let mut vertex_array = Vec::new();
-        for &index in &self.indices {
-            vertex_array.push(self.vertices[index as usize].clone());
-        }
-        vertex_array
-    }
 }
@@ -0,0 +1,372 @@
+// pipeline.rs
+#![allow(dead_code)]
+
+use crate::Uniforms;
+use crate::blend::{BlendState, Transparency};
+use crate::clipping::clip_triangle;
+use crate::culling::Culler;
+use crate::fragment::Fragment;
+use crate::framebuffer::{DepthTestMode, Framebuffer};
+use crate::light::Light;
+use crate::obj::Obj;
+use crate::rect::Rect;
+use crate::shaders::{fragment_shaders, vertex_shader, viewport_transform};
+use crate::tiles::{DrawJob, TiledRasterizer};
+use crate::vertex::Vertex;
+use crate::wireframe::PolygonMode;
+use raylib::prelude::*;
+
+/// Fixed-function state shared by every draw, plus the stats it collects
+pub struct Pipeline {
+    pub culler: Culler,
+    pub rasterizer: TiledRasterizer,
+    /// How the following draws turn their triangles into pixels, set it before a draw to pick per draw
+    pub polygon_mode: PolygonMode,
+    /// When the following draws depth test. Set `Late` before a draw whose shader
+    /// writes `fragment.depth`, so the test sees the depth the shader wrote
+    pub depth_test: DepthTestMode,
+    /// Forces one polygon mode on every draw, for debugging
+    pub polygon_mode_override: Option<PolygonMode>,
+    /// Draws the edges of every opaque draw on top of it, in `wireframe_color`
+    pub wireframe_overlay: bool,
+    /// Color of the overlay, written in sRGB like the shader palettes
+    pub wireframe_color: Vector3,
+    /// Diameter in pixels of the dots drawn by `PolygonMode::Points`
+    pub point_size: f32,
+    /// Limits the following draws to this rectangle of the framebuffer, `None` draws anywhere
+    pub scissor: Option<Rect>,
+    /// Vertices run through the vertex shader this frame
+    pub shaded_vertices: usize,
+    // Transparent draws of the current frame, waiting for `end_frame`
+    transparent: Vec<QueuedDraw>,
+}
+
+/// A transparent draw, already transformed and culled down to screen-space triangles
+struct QueuedDraw {
+    triangles: Vec<[Vertex; 3]>,
+    uniforms: Uniforms,
+    light: Light,
+    shader_type: String,
+    state: DrawState,
+    // Average screen depth of the triangles, for sorting
+    depth: f32,
+}
+
+/// One copy of the mesh drawn by `Pipeline::render_instanced`
+#[derive(Clone, Copy)]
+pub struct Instance {
+    pub model_matrix: Matrix,
+    pub shader_type: &'static str,
+    /// Free for the shader to interpret, see `Fragment::params`
+    pub params: Vector4,
+}
+
+impl Instance {
+    pub fn new(model_matrix: Matrix, shader_type: &'static str) -> Self {
+        Instance { model_matrix, shader_type, params: Vector4::new(0.0, 0.0, 0.0, 0.0) }
+    }
+
+    pub fn with_params(mut self, params: Vector4) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+/// Per-draw state captured when the draw is submitted
+#[derive(Clone, Copy)]
+struct DrawState {
+    blend: BlendState,
+    depth_test: DepthTestMode,
+    polygon_mode: PolygonMode,
+    wireframe_overlay: bool,
+    scissor: Option<Rect>,
+}
+
+/// Post-transform vertex cache: remembers the vertex shader's output for every
+/// vertex of the mesh, so a vertex shared by several triangles is shaded once per draw
+struct VertexCache {
+    entries: Vec<Option<Vertex>>,
+    misses: usize,
+    // Stamped on every vertex shaded through the cache
+    params: Vector4,
+}
+
+impl VertexCache {
+    fn new(vertex_count: usize) -> Self {
+        VertexCache { entries: vec![None; vertex_count], misses: 0, params: Vector4::new(0.0, 0.0, 0.0, 0.0) }
+    }
+
+    /// Forgets every shaded vertex, so the cache can be reused by the next instance
+    fn reset(&mut self, params: Vector4) {
+        self.entries.fill(None);
+        self.params = params;
+    }
+
+    /// The shaded vertex at `index`, running the vertex shader the first time it is asked for
+    fn get(&mut self, index: u32, vertices: &[Vertex], uniforms: &Uniforms) -> &Vertex {
+        let entry = &mut self.entries[index as usize];
+        if entry.is_none() {
+            self.misses += 1;
+        }
+        entry.get_or_insert_with(|| Vertex { params: self.params, ..vertex_shader(&vertices[index as usize], uniforms) })
+    }
+}
+
+// Average screen depth of a triangle's corners; larger is farther away
+fn triangle_depth(tri: &[Vertex; 3]) -> f32 {
+    (tri[0].transformed_position.z + tri[1].transformed_position.z + tri[2].transformed_position.z) / 3.0
+}
+
+impl Pipeline {
+    pub fn new(culler: Culler, rasterizer: TiledRasterizer) -> Self {
+        Pipeline {
+            culler,
+            rasterizer,
+            polygon_mode: PolygonMode::Fill,
+            depth_test: DepthTestMode::Early,
+            polygon_mode_override: None,
+            wireframe_overlay: false,
+            wireframe_color: Vector3::new(0.3, 1.0, 0.45),
+            point_size: 3.0,
+            scissor: None,
+            shaded_vertices: 0,
+            transparent: Vec::new(),
+        }
+    }
+
+    /// Resets the per-frame stats, call once before the first draw of a frame
+    pub fn begin_frame(&mut self) {
+        self.culler.reset_stats();
+        self.shaded_vertices = 0;
+        self.transparent.clear();
+    }
+
+    /// Draws an indexed mesh as opaque geometry, writing color and depth
+    pub fn render(
+        &mut self,
+        framebuffer: &mut Framebuffer,
+        uniforms: &Uniforms,
+        mesh: &Obj,
+        light: &Light,
+        shader_type: &str,
+    ) {
+        self.render_blended(framebuffer, uniforms, mesh, light, shader_type, BlendState::OPAQUE);
+    }
+
+    /// Draws many opaque copies of one mesh. Each instance replaces the model matrix of
+    /// `uniforms` with its own and hands its parameters to the fragment shader.
+    /// The vertex cache is shared by all the instances, and consecutive instances with
+    /// the same shader are rasterized together in a single pass over the tiles.
+    pub fn render_instanced(
+        &mut self,
+        framebuffer: &mut Framebuffer,
+        uniforms: &Uniforms,
+        mesh: &Obj,
+        instances: &[Instance],
+        light: &Light,
+    ) {
+        let state = self.draw_state(BlendState::OPAQUE);
+        let mut cache = VertexCache::new(mesh.vertices.len());
+        let mut batch = Vec::new();
+
+        for (i, instance) in instances.iter().enumerate() {
+            let instance_uniforms = Uniforms { model_matrix: instance.model_matrix, ..uniforms.clone() };
+            cache.reset(instance.params);
+            batch.extend(self.process_geometry(&instance_uniforms, mesh, &mut cache));
+
+            let batch_ends = instances.get(i + 1).is_none_or(|next| next.shader_type != instance.shader_type);
+            if batch_ends {
+                self.rasterize(framebuffer, &batch, uniforms, light, instance.shader_type, state);
+                batch.clear();
+            }
+        }
+    }
+
+    /// Draws with the given blend state. Opaque draws are rasterized right away;
+    /// transparent ones wait for `end_frame`, so they blend over the finished opaque scene.
+    /// With order-independent transparency the framebuffer sorts the fragments itself,
+    /// so transparent draws are rasterized right away too.
+    pub fn render_blended(
+        &mut self,
+        framebuffer: &mut Framebuffer,
+        uniforms: &Uniforms,
+        mesh: &Obj,
+        light: &Light,
+        shader_type: &str,
+        blend: BlendState,
+    ) {
+        let mut cache = VertexCache::new(mesh.vertices.len());
+        let mut triangles = self.process_geometry(uniforms, mesh, &mut cache);
+        let state = self.draw_state(blend);
+
+        if blend.is_transparent() && framebuffer.transparency() == Transparency::Sorted {
+            // Back to front inside the draw too, so its own overlapping triangles blend in order
+            triangles.sort_by(|a, b| triangle_depth(b).total_cmp(&triangle_depth(a)));
+            let depth = triangles.iter().map(triangle_depth).sum::<f32>() / triangles.len().max(1) as f32;
+            self.transparent.push(QueuedDraw {
+                triangles,
+                uniforms: uniforms.clone(),
+                light: light.clone(),
+                shader_type: shader_type.to_string(),
+                state,
+                depth,
+            });
+        } else {
+            self.rasterize(framebuffer, &triangles, uniforms, light, shader_type, state);
+        }
+    }
+
+    /// Draws the queued transparent draws from the farthest to the nearest.
+    /// Call once all the opaque geometry of the frame is drawn, or of each view when there are several.
+    pub fn end_frame(&mut self, framebuffer: &mut Framebuffer) {
+        let mut draws = std::mem::take(&mut self.transparent);
+        draws.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+
+        for draw in &draws {
+            self.rasterize(framebuffer, &draw.triangles, &draw.uniforms, &draw.light, &draw.shader_type, draw.state);
+        }
+    }
+
+    fn draw_state(&self, blend: BlendState) -> DrawState {
+        DrawState {
+            blend,
+            depth_test: self.depth_test,
+            polygon_mode: self.polygon_mode_override.unwrap_or(self.polygon_mode),
+            wireframe_overlay: self.wireframe_overlay,
+            scissor: self.scissor,
+        }
+    }
+
+    /// Vertex shading, primitive assembly, clipping, viewport transform and culling
+    fn process_geometry(&mut self, uniforms: &Uniforms, mesh: &Obj, cache: &mut VertexCache) -> Vec<[Vertex; 3]> {
+        // Vertex Shader and Primitive Assembly Stages: every three indices make a triangle,
+        // and the cache makes sure each vertex they point at is only shaded once
+        let misses = cache.misses;
+        let mut triangles = Vec::with_capacity(mesh.indices.len() / 3);
+        for tri in mesh.indices.chunks_exact(3) {
+            triangles.push([
+                cache.get(tri[0], &mesh.vertices, uniforms).clone(),
+                cache.get(tri[1], &mesh.vertices, uniforms).clone(),
+                cache.get(tri[2], &mesh.vertices, uniforms).clone(),
+            ]);
+        }
+        self.shaded_vertices += cache.misses - misses;
+
+        // Clipping Stage
+        let mut clipped_triangles = Vec::with_capacity(triangles.len());
+        for tri in &triangles {
+            clip_triangle(tri, &mut clipped_triangles);
+        }
+
+        // Perspective divide and viewport transform
+        let screen_triangles: Vec<[Vertex; 3]> = clipped_triangles
+            .iter()
+            .map(|tri| {
+                [
+                    viewport_transform(&tri[0], uniforms),
+                    viewport_transform(&tri[1], uniforms),
+                    viewport_transform(&tri[2], uniforms),
+                ]
+            })
+            .collect();
+
+        // Culling Stage
+        screen_triangles
+            .into_iter()
+            .filter(|tri| !self.culler.cull(&tri[0], &tri[1], &tri[2]))
+            .collect()
+    }
+
+    fn rasterize(
+        &self,
+        framebuffer: &mut Framebuffer,
+        triangles: &[[Vertex; 3]],
+        uniforms: &Uniforms,
+        light: &Light,
+        shader_type: &str,
+        state: DrawState,
+    ) {
+        let scissor = state.scissor.unwrap_or(Rect::from_size(framebuffer.width, framebuffer.height));
+
+        // Rasterization and Fragment Processing Stages, binned into tiles
+        let shader = |fragment: &mut Fragment| fragment_shaders(fragment, uniforms, shader_type);
+        self.rasterizer.draw(framebuffer, &DrawJob {
+            triangles,
+            light,
+            shader: &shader,
+            depth_test: state.depth_test,
+            blend: state.blend,
+            polygon_mode: state.polygon_mode,
+            point_size: self.point_size,
+            scissor,
+        });
+
+        // Overlay Stage: the same triangles again as flat colored lines
+        // Only for opaque draws, a translucent shell would hide the edges of what is inside it
+        if state.wireframe_overlay && !state.blend.is_transparent() && state.polygon_mode != PolygonMode::Wireframe {
+            let color = uniforms.color_space.decode(self.wireframe_color);
+            let overlay = |_: &mut Fragment| Vector4::new(color.x, color.y, color.z, 1.0);
+            self.rasterizer.draw(framebuffer, &DrawJob {
+                triangles,
+                light,
+                shader: &overlay,
+                depth_test: DepthTestMode::Early,
+                blend: BlendState::OPAQUE,
+                polygon_mode: PolygonMode::Wireframe,
+                point_size: self.point_size,
+                scissor,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::color::ColorSpace;
+    use crate::culling::{CullMode, FrontFace};
+    use crate::matrix::{create_model_matrix, create_projection_matrix, create_viewport_matrix};
+    use std::f32::consts::PI;
+
+    const SIZE: i32 = 64;
+
+    fn sphere_draw() -> (Pipeline, Framebuffer, Uniforms, Obj, Light) {
+        let mesh = Obj::load("assets/models/sphere.obj").expect("Failed to load obj");
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 6.0), Vector3::zero(), Vector3::new(0.0, 1.0, 0.0));
+        let uniforms = Uniforms {
+            model_matrix: Matrix::identity(),
+            view_matrix: camera.get_view_matrix(),
+            projection_matrix: create_projection_matrix(PI / 3.0, 1.0, 0.1, 100.0),
+            viewport_matrix: create_viewport_matrix(0.0, 0.0, SIZE as f32, SIZE as f32),
+            color_space: ColorSpace::Srgb,
+        };
+        let mut pipeline = Pipeline::new(Culler::new(CullMode::Back, FrontFace::Clockwise), TiledRasterizer::new(32, 1));
+        pipeline.begin_frame();
+        (pipeline, Framebuffer::new(SIZE, SIZE), uniforms, mesh, Light::new(Vector3::new(0.0, 0.0, 10.0)))
+    }
+
+    #[test]
+    fn indexed_draws_shade_each_vertex_once() {
+        let (mut pipeline, mut framebuffer, uniforms, mesh, light) = sphere_draw();
+        // Neighbouring triangles share vertices, so there are more indices than vertices
+        assert!(mesh.indices.len() > mesh.vertices.len());
+
+        pipeline.render(&mut framebuffer, &uniforms, &mesh, &light, "moon");
+        assert_eq!(pipeline.shaded_vertices, mesh.vertices.len());
+    }
+
+    #[test]
+    fn every_instance_shades_its_own_vertices() {
+        let (mut pipeline, mut framebuffer, uniforms, mesh, light) = sphere_draw();
+        let instances: Vec<Instance> = (0..3)
+            .map(|i| Instance::new(create_model_matrix(Vector3::new(i as f32 - 1.0, 0.0, 0.0), 0.4, Vector3::zero()), "moon"))
+            .collect();
+
+        pipeline.render_instanced(&mut framebuffer, &uniforms, &mesh, &instances, &light);
+        assert_eq!(pipeline.shaded_vertices, 3 * mesh.vertices.len());
+
+        pipeline.begin_frame();
+        assert_eq!(pipeline.shaded_vertices, 0);
+    }
+}
@@ -0,0 +1,456 @@
+// postprocess.rs
+#![allow(dead_code)]
+
+use raylib::prelude::*;
+
+/// What a pass works on: the tone mapped image, one color per pixel in [0, 1]
+/// row by row and still in the framebuffer's color space (the sRGB encode comes after), plus the depth of every pixel (`f32::INFINITY` where nothing was drawn)
+pub struct Frame<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub color: &'a mut [Vector3],
+    pub depth: &'a [f32],
+    /// Counts the frames the chain has run on, for passes that animate
+    pub index: u64,
+}
+
+/// A full-screen effect applied after tone mapping
+pub trait Pass {
+    /// Short name used to toggle the pass and in messages
+    fn name(&self) -> &'static str;
+    fn apply(&mut self, frame: &mut Frame);
+}
+
+struct Entry {
+    pass: Box<dyn Pass>,
+    enabled: bool,
+}
+
+/// An ordered list of passes, each of which can be switched on and off.
+/// Passes run in the order they were added, each one seeing the output of the previous one.
+#[derive(Default)]
+pub struct PostProcess {
+    entries: Vec<Entry>,
+    frame: u64,
+}
+
+/// Names of the passes in `PostProcess::standard`, in the order they run
+pub const STANDARD_PASSES: [&str; 6] = ["fxaa", "chromatic-aberration", "color-grading", "vignette", "film-grain", "gamma"];
+
+impl PostProcess {
+    pub fn new() -> Self {
+        PostProcess::default()
+    }
+
+    /// Every built-in pass with its default settings, all of them off.
+    /// FXAA goes first so it sees clean edges, grain and gamma last.
+    pub fn standard() -> Self {
+        let mut post_process = PostProcess::new();
+        post_process.push(Fxaa::default(), false);
+        post_process.push(ChromaticAberration::default(), false);
+        post_process.push(ColorGrading::default(), false);
+        post_process.push(Vignette::default(), false);
+        post_process.push(FilmGrain::default(), false);
+        post_process.push(Gamma::default(), false);
+        post_process
+    }
+
+    /// Appends a pass to the end of the chain
+    pub fn push(&mut self, pass: impl Pass + 'static, enabled: bool) {
+        self.entries.push(Entry { pass: Box::new(pass), enabled });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// True when at least one pass is on
+    pub fn is_active(&self) -> bool {
+        self.entries.iter().any(|entry| entry.enabled)
+    }
+
+    /// Name and state of every pass, in order
+    pub fn passes(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        self.entries.iter().map(|entry| (entry.pass.name(), entry.enabled))
+    }
+
+    /// Switches every pass called `name`, returns false when there is none
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for entry in self.entries.iter_mut().filter(|entry| entry.pass.name() == name) {
+            entry.enabled = enabled;
+            found = true;
+        }
+        found
+    }
+
+    /// Flips the pass at `index`, returning its name and new state
+    pub fn toggle(&mut self, index: usize) -> Option<(&'static str, bool)> {
+        let entry = self.entries.get_mut(index)?;
+        entry.enabled = !entry.enabled;
+        Some((entry.pass.name(), entry.enabled))
+    }
+
+    /// Runs the enabled passes over `color` in order
+    pub fn run(&mut self, color: &mut [Vector3], depth: &[f32], width: usize, height: usize) {
+        let mut frame = Frame { width, height, color, depth, index: self.frame };
+        for entry in self.entries.iter_mut().filter(|entry| entry.enabled) {
+            entry.pass.apply(&mut frame);
+        }
+        self.frame += 1;
+    }
+}
+
+#[inline]
+fn luma(color: Vector3) -> f32 {
+    color.x * 0.299 + color.y * 0.587 + color.z * 0.114
+}
+
+#[inline]
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Bilinear lookup with pixel centers on integer coordinates, edges clamped
+fn sample<T>(values: &[T], width: usize, height: usize, x: f32, y: f32) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x.fract(), y.fract());
+
+    let top = values[y0 * width + x0] * (1.0 - tx) + values[y0 * width + x1] * tx;
+    let bottom = values[y1 * width + x0] * (1.0 - tx) + values[y1 * width + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Distance of a pixel from the center of the frame, 0 in the middle and 1 in the corners,
+/// along with its x and y parts on the same scale
+fn from_center(frame: &Frame, x: usize, y: usize) -> (f32, f32, f32) {
+    let (cx, cy) = ((frame.width - 1) as f32 * 0.5, (frame.height - 1) as f32 * 0.5);
+    let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+    let corner = (cx * cx + cy * cy).sqrt().max(f32::EPSILON);
+    ((dx * dx + dy * dy).sqrt() / corner, dx / corner, dy / corner)
+}
+
+/// Darkens the image towards the corners
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette {
+    /// How dark the corners get, 0 to 1
+    pub strength: f32,
+    /// Distance from the center (1 is a corner) where the darkening starts
+    pub radius: f32,
+    /// How far past `radius` it takes to reach full strength
+    pub softness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette {
+            strength: 0.6,
+            radius: 0.45,
+            softness: 0.55,
+        }
+    }
+}
+
+impl Pass for Vignette {
+    fn name(&self) -> &'static str {
+        "vignette"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let (distance, _, _) = from_center(frame, x, y);
+                let darkening = self.strength * smoothstep(self.radius, self.radius + self.softness, distance);
+                frame.color[y * frame.width + x] *= 1.0 - darkening;
+            }
+        }
+    }
+}
+
+/// Noise that changes every frame, strongest in the mid tones like film grain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilmGrain {
+    /// Largest change the noise makes to a channel
+    pub intensity: f32,
+    /// Keep the same noise every frame instead of changing it
+    pub frozen: bool,
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        FilmGrain {
+            intensity: 0.08,
+            frozen: false,
+        }
+    }
+}
+
+/// PCG-style integer hash of a pixel and a seed, mapped to [-0.5, 0.5]
+fn noise(x: u32, y: u32, seed: u32) -> f32 {
+    let mut state = x.wrapping_mul(0x8da6b343) ^ y.wrapping_mul(0xd8163841) ^ seed.wrapping_mul(0xcb1ab31f);
+    state = state.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    ((word >> 22) ^ word) as f32 / u32::MAX as f32 - 0.5
+}
+
+impl Pass for FilmGrain {
+    fn name(&self) -> &'static str {
+        "film-grain"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        let seed = if self.frozen { 0 } else { frame.index as u32 };
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let pixel = &mut frame.color[y * frame.width + x];
+                // Pure black and white show no grain
+                let l = luma(*pixel).clamp(0.0, 1.0);
+                let weight = 1.0 - (2.0 * l - 1.0) * (2.0 * l - 1.0);
+                let grain = noise(x as u32, y as u32, seed) * 2.0 * self.intensity * weight;
+                *pixel += Vector3::new(grain, grain, grain);
+            }
+        }
+    }
+}
+
+/// Splits the red and blue channels apart towards the edges, like a cheap lens
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChromaticAberration {
+    /// How far apart red and blue are in the corners, in pixels
+    pub offset: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        ChromaticAberration { offset: 4.0 }
+    }
+}
+
+impl Pass for ChromaticAberration {
+    fn name(&self) -> &'static str {
+        "chromatic-aberration"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        let (width, height) = (frame.width, frame.height);
+        // Only red and blue move, so only they are copied out
+        let red: Vec<f32> = frame.color.iter().map(|color| color.x).collect();
+        let blue: Vec<f32> = frame.color.iter().map(|color| color.z).collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                // Grows with the distance from the center, so the middle stays sharp
+                let (_, dx, dy) = from_center(frame, x, y);
+                let (shift_x, shift_y) = (dx * self.offset * 0.5, dy * self.offset * 0.5);
+
+                let pixel = &mut frame.color[y * width + x];
+                pixel.x = sample(&red, width, height, x as f32 + shift_x, y as f32 + shift_y);
+                pixel.z = sample(&blue, width, height, x as f32 - shift_x, y as f32 - shift_y);
+            }
+        }
+    }
+}
+
+/// Fast approximate anti-aliasing, after Lottes' FXAA 3.11: finds edges from the
+/// luma contrast, walks along them to their ends and blends across
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fxaa {
+    /// Contrast relative to the brightest neighbor needed to count as an edge
+    pub edge_threshold: f32,
+    /// Contrast below which dark areas are left alone
+    pub edge_threshold_min: f32,
+    /// How much single-pixel details get smoothed, 0 to 1
+    pub subpixel: f32,
+    /// Pixels searched in each direction for the end of an edge
+    pub search_steps: usize,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 0.75,
+            search_steps: 12,
+        }
+    }
+}
+
+impl Pass for Fxaa {
+    fn name(&self) -> &'static str {
+        "fxaa"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        let (width, height) = (frame.width, frame.height);
+        let source = frame.color.to_vec();
+        let lumas: Vec<f32> = source.iter().map(|&color| luma(color)).collect();
+        let at = |x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            lumas[y * width + x]
+        };
+
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let center = at(x, y);
+                let (north, south, west, east) = (at(x, y - 1), at(x, y + 1), at(x - 1, y), at(x + 1, y));
+                let highest = center.max(north).max(south).max(west).max(east);
+                let lowest = center.min(north).min(south).min(west).min(east);
+                let range = highest - lowest;
+                if range < self.edge_threshold_min.max(highest * self.edge_threshold) {
+                    continue;
+                }
+
+                let (north_west, north_east) = (at(x - 1, y - 1), at(x + 1, y - 1));
+                let (south_west, south_east) = (at(x - 1, y + 1), at(x + 1, y + 1));
+
+                // Blend for details thinner than a pixel, from how much the center
+                // differs from the average of its neighborhood
+                let average = (2.0 * (north + south + west + east) + north_west + north_east + south_west + south_east) / 12.0;
+                let subpixel = ((average - center).abs() / range).clamp(0.0, 1.0);
+                let subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+                let subpixel = subpixel * subpixel * self.subpixel;
+
+                // Horizontal edges change the most from row to row
+                let horizontal = (north_west - 2.0 * west + south_west).abs()
+                    + 2.0 * (north - 2.0 * center + south).abs()
+                    + (north_east - 2.0 * east + south_east).abs()
+                    >= (north_west - 2.0 * north + north_east).abs()
+                        + 2.0 * (west - 2.0 * center + east).abs()
+                        + (south_west - 2.0 * south + south_east).abs();
+
+                // Which side of the pixel the edge runs along
+                let (negative, positive) = if horizontal { (north, south) } else { (west, east) };
+                let (negative_gradient, positive_gradient) = ((negative - center).abs(), (positive - center).abs());
+                let (step, side_luma) = if negative_gradient >= positive_gradient {
+                    (-1.0, negative)
+                } else {
+                    (1.0, positive)
+                };
+                let gradient = 0.25 * negative_gradient.max(positive_gradient);
+                let edge_luma = 0.5 * (center + side_luma);
+
+                // Walk along the edge, half a pixel towards that side, until the luma changes
+                let (start_x, start_y, along_x, along_y) = if horizontal {
+                    (x as f32, y as f32 + step * 0.5, 1.0, 0.0)
+                } else {
+                    (x as f32 + step * 0.5, y as f32, 0.0, 1.0)
+                };
+                let walk = |direction: f32| {
+                    let mut end = 0.0;
+                    for i in 1..=self.search_steps {
+                        let distance = i as f32;
+                        end = sample(&lumas, width, height, start_x + along_x * distance * direction, start_y + along_y * distance * direction)
+                            - edge_luma;
+                        if end.abs() >= gradient {
+                            return (distance, end);
+                        }
+                    }
+                    (self.search_steps as f32, end)
+                };
+                let (backward_distance, backward_end) = walk(-1.0);
+                let (forward_distance, forward_end) = walk(1.0);
+
+                // Pixels near the end of an edge sit under the steep part of the stair and get blended the most
+                let (distance, end) = if backward_distance < forward_distance {
+                    (backward_distance, backward_end)
+                } else {
+                    (forward_distance, forward_end)
+                };
+                let edge_offset = if (end < 0.0) != (center < edge_luma) {
+                    0.5 - distance / (backward_distance + forward_distance)
+                } else {
+                    0.0
+                };
+
+                let offset = edge_offset.max(subpixel) * step;
+                let (sample_x, sample_y) = if horizontal { (x as f32, y as f32 + offset) } else { (x as f32 + offset, y as f32) };
+                frame.color[y as usize * width + x as usize] = sample(&source, width, height, sample_x, sample_y);
+            }
+        }
+    }
+}
+
+/// Simple color grade: brightness, contrast around mid gray, saturation and a tint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorGrading {
+    /// Added to every channel
+    pub brightness: f32,
+    /// 1 leaves the image as is, higher pushes colors away from mid gray
+    pub contrast: f32,
+    /// 0 is grayscale, 1 leaves the image as is
+    pub saturation: f32,
+    /// Multiplies each channel
+    pub tint: Vector3,
+}
+
+impl Default for ColorGrading {
+    /// A slightly punchier, warmer look
+    fn default() -> Self {
+        ColorGrading {
+            brightness: 0.0,
+            contrast: 1.15,
+            saturation: 1.2,
+            tint: Vector3::new(1.05, 1.0, 0.93),
+        }
+    }
+}
+
+impl Pass for ColorGrading {
+    fn name(&self) -> &'static str {
+        "color-grading"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        let mid_gray = Vector3::new(0.5, 0.5, 0.5);
+        for pixel in frame.color.iter_mut() {
+            let color = (*pixel - mid_gray) * self.contrast + mid_gray;
+            let l = luma(color);
+            let gray = Vector3::new(l, l, l);
+            let color = gray + (color - gray) * self.saturation;
+            let color = color * self.tint;
+            *pixel = color + Vector3::new(self.brightness, self.brightness, self.brightness);
+        }
+    }
+}
+
+/// Raises every channel to `1 / gamma`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gamma {
+    pub gamma: f32,
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Gamma { gamma: 2.2 }
+    }
+}
+
+impl Pass for Gamma {
+    fn name(&self) -> &'static str {
+        "gamma"
+    }
+
+    fn apply(&mut self, frame: &mut Frame) {
+        let exponent = 1.0 / self.gamma;
+        for pixel in frame.color.iter_mut() {
+            *pixel = Vector3::new(
+                pixel.x.max(0.0).powf(exponent),
+                pixel.y.max(0.0).powf(exponent),
+                pixel.z.max(0.0).powf(exponent),
+            );
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// presenter.rs
+use crate::framebuffer::Framebuffer;
+use raylib::core::error::Error;
+use raylib::prelude::*;
+
+/// Shows the software framebuffer in a raylib window.
+/// Only needed when rendering on screen; the framebuffer itself knows nothing about raylib.
+///
+/// Frames go into one GPU texture that is created with the presenter and updated in
+/// place every frame. It is only recreated when the size of the presented buffer changes.
+/// The texture is scaled to fit the window, keeping its aspect ratio, so a resized
+/// window still shows the whole frame.
+pub struct RaylibPresenter {
+    texture: Texture2D,
+    filter: UpscaleFilter,
+}
+
+/// How the frame is filtered when it is drawn bigger or smaller than it was rendered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Blocky, every pixel of the frame stays a sharp square
+    Nearest,
+    /// Smooth, interpolates between neighboring pixels
+    Bilinear,
+}
+
+impl UpscaleFilter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(UpscaleFilter::Nearest),
+            "bilinear" => Some(UpscaleFilter::Bilinear),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UpscaleFilter::Nearest => "nearest",
+            UpscaleFilter::Bilinear => "bilinear",
+        }
+    }
+
+    fn texture_filter(self) -> TextureFilter {
+        match self {
+            UpscaleFilter::Nearest => TextureFilter::TEXTURE_FILTER_POINT,
+            UpscaleFilter::Bilinear => TextureFilter::TEXTURE_FILTER_BILINEAR,
+        }
+    }
+}
+
+// An RGBA8 texture of the given size, uploaded once from a blank image
+fn create_texture(
+    d: &mut RaylibHandle,
+    thread: &RaylibThread,
+    width: i32,
+    height: i32,
+    filter: UpscaleFilter,
+) -> Result<Texture2D, Error> {
+    let image = Image::gen_image_color(width, height, Color::BLACK);
+    let texture = d.load_texture_from_image(thread, &image)?;
+    texture.set_texture_filter(thread, filter.texture_filter());
+    Ok(texture)
+}
+
+impl RaylibPresenter {
+    /// Creates the texture frames are uploaded into; call once the window is open
+    pub fn new(
+        d: &mut RaylibHandle,
+        thread: &RaylibThread,
+        width: i32,
+        height: i32,
+        filter: UpscaleFilter,
+    ) -> Result<Self, Error> {
+        Ok(RaylibPresenter {
+            texture: create_texture(d, thread, width, height, filter)?,
+            filter,
+        })
+    }
+
+    pub fn set_filter(&mut self, thread: &RaylibThread, filter: UpscaleFilter) {
+        self.filter = filter;
+        self.texture.set_texture_filter(thread, filter.texture_filter());
+    }
+
+    pub fn filter(&self) -> UpscaleFilter {
+        self.filter
+    }
+
+    pub fn present(&mut self, framebuffer: &Framebuffer, d: &mut RaylibHandle, thread: &RaylibThread) -> Result<(), Error> {
+        self.present_rgba(&framebuffer.color_buffer, framebuffer.width, framebuffer.height, d, thread)
+    }
+
+    /// Shows any tightly packed RGBA8 buffer of `width` x `height`, e.g. a debug view.
+    /// Fails when the texture can't be recreated or `rgba` isn't `width * height * 4` bytes.
+    /// The window is drawn even then, with the last frame that did upload, so it keeps
+    /// swapping buffers and handling input while the error lasts.
+    pub fn present_rgba(
+        &mut self,
+        rgba: &[u8],
+        width: i32,
+        height: i32,
+        d: &mut RaylibHandle,
+        thread: &RaylibThread,
+    ) -> Result<(), Error> {
+        let uploaded = self.upload(rgba, width, height, d, thread);
+
+        // Largest scale that fits the window, centered with black bars on the other axis
+        let (texture_width, texture_height) = (self.texture.width() as f32, self.texture.height() as f32);
+        let (screen_width, screen_height) = (d.get_screen_width() as f32, d.get_screen_height() as f32);
+        let scale = (screen_width / texture_width).min(screen_height / texture_height);
+        let (dest_width, dest_height) = (texture_width * scale, texture_height * scale);
+        let dest = Rectangle::new(
+            (screen_width - dest_width) / 2.0,
+            (screen_height - dest_height) / 2.0,
+            dest_width,
+            dest_height,
+        );
+
+        let mut d = d.begin_drawing(thread);
+        d.clear_background(Color::BLACK);
+        d.draw_texture_pro(
+            &self.texture,
+            Rectangle::new(0.0, 0.0, texture_width, texture_height),
+            dest,
+            Vector2::zero(),
+            0.0,
+            Color::WHITE,
+        );
+        uploaded
+    }
+
+    // Copies `rgba` into the texture, recreating it first when the size changed.
+    // On failure the texture keeps the last frame that uploaded.
+    fn upload(&mut self, rgba: &[u8], width: i32, height: i32, d: &mut RaylibHandle, thread: &RaylibThread) -> Result<(), Error> {
+        if self.texture.width() != width || self.texture.height() != height {
+            let mut texture = create_texture(d, thread, width, height, self.filter)?;
+            texture.update_texture(rgba)?;
+            self.texture = texture;
+            return Ok(());
+        }
+        self.texture.update_texture(rgba)
+    }
+}
@@ -0,0 +1,146 @@
+// resolution.rs
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+// Weight of the newest frame in the running average of the frame time
+const SMOOTHING: f32 = 0.2;
+// Frames averaged after a change before the scale may change again
+const SETTLE_FRAMES: u32 = 8;
+// The scale only goes up while frames are this much faster than the target,
+// so it doesn't bounce between two sizes right at the limit
+const HEADROOM: f32 = 0.15;
+// Scales are rounded to multiples of this, so small changes don't reallocate the framebuffer
+const STEP: f32 = 0.05;
+
+/// Dynamic resolution: picks the render scale that keeps the frame time near `target_ms`.
+/// Rendering cost grows with the covered pixels, that is with the square of the scale,
+/// so the scale moves by the square root of how far the averaged frame time is off.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicResolution {
+    /// Frame time to hold, in milliseconds
+    pub target_ms: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    scale: f32,
+    // Running average of the frame time in milliseconds, since the last change
+    average_ms: f32,
+    samples: u32,
+}
+
+impl DynamicResolution {
+    /// Starts at `scale`, kept within `min_scale..=max_scale`
+    pub fn new(target_ms: f32, min_scale: f32, max_scale: f32, scale: f32) -> Self {
+        DynamicResolution {
+            target_ms,
+            min_scale,
+            max_scale,
+            scale: scale.clamp(min_scale, max_scale),
+            average_ms: 0.0,
+            samples: 0,
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Average frame time measured at the current scale, in milliseconds
+    pub fn average_ms(&self) -> f32 {
+        self.average_ms
+    }
+
+    /// Records how long the last frame took and returns the scale for the next one
+    pub fn update(&mut self, frame_time: Duration) -> f32 {
+        let ms = frame_time.as_secs_f32() * 1000.0;
+        // The first frame after a change pays for the new buffers, so it isn't counted
+        self.samples += 1;
+        match self.samples {
+            1 => return self.scale,
+            2 => self.average_ms = ms,
+            _ => self.average_ms += (ms - self.average_ms) * SMOOTHING,
+        }
+        if self.samples < SETTLE_FRAMES || self.average_ms <= 0.0 {
+            return self.scale;
+        }
+
+        let too_slow = self.average_ms > self.target_ms;
+        let fast_enough = self.average_ms < self.target_ms * (1.0 - HEADROOM);
+        if !too_slow && !fast_enough {
+            return self.scale;
+        }
+
+        let ideal = self.scale * (self.target_ms / self.average_ms).sqrt();
+        let rounded = if too_slow { (ideal / STEP).floor() } else { (ideal / STEP).round() } * STEP;
+        let scale = rounded.clamp(self.min_scale, self.max_scale);
+        if (scale - self.scale).abs() > STEP * 0.5 {
+            self.scale = scale;
+            self.samples = 0;
+        }
+        self.scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds `frames` frames that each took `ms` and returns the last scale
+    fn feed(controller: &mut DynamicResolution, ms: f32, frames: u32) -> f32 {
+        let mut scale = controller.scale();
+        for _ in 0..frames {
+            scale = controller.update(Duration::from_secs_f32(ms / 1000.0));
+        }
+        scale
+    }
+
+    fn assert_scale(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "scale {} != {}", actual, expected);
+    }
+
+    #[test]
+    fn waits_for_the_settle_window_after_every_change() {
+        let mut controller = DynamicResolution::new(10.0, 0.25, 2.0, 1.0);
+        assert_scale(feed(&mut controller, 40.0, SETTLE_FRAMES - 1), 1.0);
+        assert_scale(feed(&mut controller, 40.0, 1), 0.5);
+        // The change restarts the window
+        assert_scale(feed(&mut controller, 40.0, SETTLE_FRAMES - 1), 0.5);
+        assert_scale(feed(&mut controller, 40.0, 1), 0.25);
+    }
+
+    #[test]
+    fn holds_the_scale_inside_the_headroom_band() {
+        let mut controller = DynamicResolution::new(10.0, 0.25, 2.0, 1.0);
+        // Between the target and HEADROOM below it nothing changes, however long it lasts
+        assert_scale(feed(&mut controller, 9.0, SETTLE_FRAMES * 4), 1.0);
+        assert_scale(feed(&mut controller, 10.0, SETTLE_FRAMES * 4), 1.0);
+
+        let mut controller = DynamicResolution::new(10.0, 0.25, 2.0, 1.0);
+        assert!(feed(&mut controller, 8.0, SETTLE_FRAMES) > 1.0);
+    }
+
+    #[test]
+    fn rounds_down_when_too_slow_and_to_the_nearest_step_otherwise() {
+        // sqrt(10 / 10.5) = 0.976 would round back to 1, too slow drops to the step below
+        let mut controller = DynamicResolution::new(10.0, 0.25, 2.0, 1.0);
+        assert_scale(feed(&mut controller, 10.5, SETTLE_FRAMES), 0.95);
+
+        // 0.6 * sqrt(10 / 4) = 0.949 rounds up to 0.95 instead of flooring to 0.9
+        let mut controller = DynamicResolution::new(10.0, 0.25, 2.0, 0.6);
+        assert_scale(feed(&mut controller, 4.0, SETTLE_FRAMES), 0.95);
+    }
+
+    #[test]
+    fn clamps_to_the_scale_range() {
+        let controller = DynamicResolution::new(10.0, 0.5, 1.5, 3.0);
+        assert_scale(controller.scale(), 1.5);
+
+        let mut controller = DynamicResolution::new(10.0, 0.5, 1.5, 1.0);
+        assert_scale(feed(&mut controller, 1000.0, SETTLE_FRAMES), 0.5);
+        // Already at the bottom: the next window changes nothing
+        assert_scale(feed(&mut controller, 1000.0, SETTLE_FRAMES * 2), 0.5);
+
+        let mut controller = DynamicResolution::new(10.0, 0.5, 1.5, 1.0);
+        assert_scale(feed(&mut controller, 0.1, SETTLE_FRAMES), 1.5);
+    }
+}
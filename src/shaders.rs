@@ -1,4 +1,5 @@
 use crate::Uniforms;
+use crate::color::ColorSpace;
 use crate::fragment::Fragment;
 use crate::vertex::Vertex;
 use raylib::prelude::*;
@@ -53,35 +54,46 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
     let view_position = multiply_matrix_vector4(&uniforms.view_matrix, &world_position_vec4);
     let clip_position = multiply_matrix_vector4(&uniforms.projection_matrix, &view_position);
 
-    let ndc = if clip_position.w != 0.0 {
-        Vector3::new(
-            clip_position.x / clip_position.w,
-            clip_position.y / clip_position.w,
-            clip_position.z / clip_position.w,
-        )
-    } else {
-        Vector3::new(clip_position.x, clip_position.y, clip_position.z)
-    };
-
-    let ndc_vec4 = Vector4::new(ndc.x, ndc.y, ndc.z, 1.0);
-    let screen_position = multiply_matrix_vector4(&uniforms.viewport_matrix, &ndc_vec4);
-
-    let transformed_position =
-        Vector3::new(screen_position.x, screen_position.y, screen_position.z);
-
+    // The perspective divide is deferred until after clipping (see `viewport_transform`),
+    // so vertices behind the eye never get projected.
     Vertex {
         position: vertex.position,
         normal: vertex.normal,
         tex_coords: vertex.tex_coords,
         color: vertex.color,
-        transformed_position,
+        transformed_position: Vector3::zero(),
         transformed_normal: transform_normal(&vertex.normal, &uniforms.model_matrix),
         world_position,
+        clip_position,
+        inv_w: 1.0,
+        params: vertex.params,
+    }
+}
+
+/// Perspective divide followed by the viewport transform.
+/// Expects a vertex that already went through the clipper, so `w` is positive.
+/// 1/w is kept on the vertex for perspective-correct interpolation.
+pub fn viewport_transform(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
+    let clip_position = vertex.clip_position;
+    let inv_w = 1.0 / clip_position.w;
+    let ndc = Vector3::new(
+        clip_position.x * inv_w,
+        clip_position.y * inv_w,
+        clip_position.z * inv_w,
+    );
+
+    let ndc_vec4 = Vector4::new(ndc.x, ndc.y, ndc.z, 1.0);
+    let screen_position = multiply_matrix_vector4(&uniforms.viewport_matrix, &ndc_vec4);
+
+    Vertex {
+        transformed_position: Vector3::new(screen_position.x, screen_position.y, screen_position.z),
+        inv_w,
+        ..vertex.clone()
     }
 }
 
 
-pub fn earth_shader(fragment: &Fragment) -> Vector3 {
+pub fn earth_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
     let uv = fragment.position * 0.015;
     
     let noise1 = (uv.x * 3.0).sin() * (uv.y * 2.5).cos();
@@ -109,9 +121,10 @@ pub fn earth_shader(fragment: &Fragment) -> Vector3 {
     } else {
         deep_ocean
     };
+    let base_color = color_space.decode(base_color);
     
     let cloud_pattern = ((uv.x * 7.0 + 200.0).sin() * (uv.y * 6.0 + 150.0).cos()).abs();
-    let clouds = Vector3::new(1.0, 1.0, 1.0) * 0.3;
+    let clouds = color_space.decode(Vector3::new(1.0, 1.0, 1.0) * 0.3);
     
     let color_with_clouds = if cloud_pattern > 0.75 {
         base_color * 0.7 + clouds
@@ -122,7 +135,7 @@ pub fn earth_shader(fragment: &Fragment) -> Vector3 {
     color_with_clouds * 1.2
 }
 
-pub fn jupiter_shader(fragment: &Fragment) -> Vector3 {
+pub fn jupiter_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
     let uv = fragment.position * 0.01;
     
     let band_pos = uv.y * 15.0;
@@ -138,7 +151,7 @@ pub fn jupiter_shader(fragment: &Fragment) -> Vector3 {
     let spot_y = uv.y - 250.0;
     let spot_dist = (spot_x * spot_x + spot_y * spot_y * 4.0).sqrt();
     let red_spot = if spot_dist < 50.0 {
-        Vector3::new(0.4, 0.1, 0.05) * (1.0 - spot_dist / 50.0)
+        color_space.decode(Vector3::new(0.4, 0.1, 0.05)) * (1.0 - spot_dist / 50.0)
     } else {
         Vector3::new(0.0, 0.0, 0.0)
     };
@@ -164,11 +177,12 @@ pub fn jupiter_shader(fragment: &Fragment) -> Vector3 {
     } else {
         deep_brown
     };
+    let base_color = color_space.decode(base_color);
     
     (base_color + red_spot) * 1.1
 }
 
-pub fn namek_shader(fragment: &Fragment) -> Vector3 {
+pub fn namek_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
     let uv = fragment.position * 0.012;
     
     let noise1 = (uv.x * 4.0).sin() * (uv.y * 3.5).cos();
@@ -196,13 +210,14 @@ pub fn namek_shader(fragment: &Fragment) -> Vector3 {
     } else {
         deep_water
     };
+    let base_color = color_space.decode(base_color);
     
-    let glow = Vector3::new(0.3, 0.5, 0.3) * 0.2;
+    let glow = color_space.decode(Vector3::new(0.3, 0.5, 0.3)) * 0.2;
     
     (base_color + glow) * 1.4
 }
 
-pub fn sun_shader(fragment: &Fragment) -> Vector3 {
+pub fn sun_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
     let uv = fragment.position * 0.008;
     
     let noise1 = (uv.x * 3.0).sin() * (uv.y * 2.8).cos();
@@ -232,11 +247,12 @@ pub fn sun_shader(fragment: &Fragment) -> Vector3 {
     } else {
         red_orange
     };
+    let base_color = color_space.decode(base_color);
     
     base_color * 2.5
 }
 
-pub fn moon_shader(fragment: &Fragment) -> Vector3 {
+pub fn moon_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
     let uv = fragment.position * 0.025;
     
     let noise1 = (uv.x * 5.0).sin() * (uv.y * 4.5).cos();
@@ -258,29 +274,80 @@ pub fn moon_shader(fragment: &Fragment) -> Vector3 {
     } else {
         dark_gray
     };
+    let base_color = color_space.decode(base_color);
     
     base_color * 1.0
 }
 
+/// Haze around the Earth: pale blue that thins out on the night side
+pub fn atmosphere_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector4 {
+    let haze = color_space.decode(Vector3::new(0.55, 0.75, 1.0));
+    let lighting_intensity = fragment.color.x;
+    Vector4::new(haze.x, haze.y, haze.z, 0.35 * lighting_intensity)
+}
+
+/// Asteroid rock. Reads its per-instance parameters: `params.x` darkens or
+/// lightens the stone and `params.y` mixes gray towards rusty brown.
+pub fn rock_shader(fragment: &Fragment, color_space: ColorSpace) -> Vector3 {
+    let uv = fragment.position * 0.05;
+    let speckle = ((uv.x * 9.0).sin() * (uv.y * 7.0 + 30.0).cos()).abs();
+
+    let gray = Vector3::new(0.42, 0.4, 0.38);
+    let brown = Vector3::new(0.45, 0.3, 0.2);
+    let rust = fragment.params.y.clamp(0.0, 1.0);
+    let tone = 0.7 + 0.6 * fragment.params.x.clamp(0.0, 1.0);
+
+    let base_color = (gray * (1.0 - rust) + brown * rust) * tone * (0.85 + 0.15 * speckle);
+    color_space.decode(base_color)
+}
+
+// The procedural patterns were tuned in the pixels of the original 1300x900 window
+const PATTERN_REFERENCE_HEIGHT: f32 = 900.0;
+
+/// Moves a fragment from framebuffer pixels to pixels of a `PATTERN_REFERENCE_HEIGHT` tall
+/// view, measured from the corner of the view that `viewport_matrix` maps onto. The patterns
+/// then keep their size and place at any window size, render scale or view layout.
+fn pattern_position(position: Vector2, viewport_matrix: &Matrix) -> Vector2 {
+    // See `create_viewport_matrix`: m0 and -m5 are half the view's size, m12 and m13 its center
+    let (half_width, half_height) = (viewport_matrix.m0, -viewport_matrix.m5);
+    if half_height <= 0.0 {
+        return position;
+    }
+    let corner = Vector2::new(viewport_matrix.m12 - half_width, viewport_matrix.m13 - half_height);
+    (position - corner) * (PATTERN_REFERENCE_HEIGHT / (2.0 * half_height))
+}
+
+/// Runs the planet's shader and lights the result. The palettes are written in sRGB
+/// and decoded into `uniforms.color_space`, so the lighting happens in that space.
+/// The shaders see `fragment.position` in view pixels, see `pattern_position`.
+/// Returns RGBA; the planets are opaque, only the atmosphere has alpha below 1.
 pub fn fragment_shaders(
-    fragment: &Fragment,
-    _uniforms: &Uniforms,
+    fragment: &mut Fragment,
+    uniforms: &Uniforms,
     shader_type: &str,
-) -> Vector3 {
-    let base_color = match shader_type {
-        "earth" => earth_shader(fragment),
-        "jupiter" => jupiter_shader(fragment),
-        "namek" => namek_shader(fragment),
-        "sun" => sun_shader(fragment),
-        "moon" => moon_shader(fragment),
-        _ => earth_shader(fragment),
+) -> Vector4 {
+    fragment.position = pattern_position(fragment.position, &uniforms.viewport_matrix);
+
+    let (base_color, alpha) = match shader_type {
+        "earth" => (earth_shader(fragment, uniforms.color_space), 1.0),
+        "jupiter" => (jupiter_shader(fragment, uniforms.color_space), 1.0),
+        "namek" => (namek_shader(fragment, uniforms.color_space), 1.0),
+        "sun" => (sun_shader(fragment, uniforms.color_space), 1.0),
+        "moon" => (moon_shader(fragment, uniforms.color_space), 1.0),
+        "rock" => (rock_shader(fragment, uniforms.color_space), 1.0),
+        "atmosphere" => {
+            let haze = atmosphere_shader(fragment, uniforms.color_space);
+            (Vector3::new(haze.x, haze.y, haze.z), haze.w)
+        }
+        _ => (earth_shader(fragment, uniforms.color_space), 1.0),
     };
     
     if shader_type == "sun" {
-        return base_color;
+        return Vector4::new(base_color.x, base_color.y, base_color.z, alpha);
     }
     
     let lighting_intensity = fragment.color.x;
+    let lit = base_color * lighting_intensity;
     
-    base_color * lighting_intensity
+    Vector4::new(lit.x, lit.y, lit.z, alpha)
 }
@@ -0,0 +1,264 @@
+// tiles.rs
+#![allow(dead_code)]
+
+use crate::blend::BlendState;
+use crate::fragment::Fragment;
+use crate::framebuffer::{DepthTestMode, Framebuffer, FramebufferBand};
+use crate::light::Light;
+use crate::rect::Rect;
+use crate::triangle::triangle;
+use crate::vertex::Vertex;
+use crate::wireframe::{self, PolygonMode};
+use raylib::prelude::*;
+use std::collections::HashSet;
+use std::thread;
+
+/// Binning rasterizer: the screen is split into square tiles, every triangle is
+/// binned into the tiles its bounding box touches, and each row of tiles is
+/// rasterized, depth tested and shaded by one worker thread.
+///
+/// A pixel is only ever touched by the worker that owns its row of tiles, and
+/// bins keep triangles in submission order, so the output is the same for any
+/// thread count.
+pub struct TiledRasterizer {
+    pub tile_size: i32,
+    pub threads: usize,
+}
+
+/// One draw as every worker sees it
+pub struct DrawJob<'a, S> {
+    pub triangles: &'a [[Vertex; 3]],
+    pub light: &'a Light,
+    pub shader: &'a S,
+    pub depth_test: DepthTestMode,
+    pub blend: BlendState,
+    pub polygon_mode: PolygonMode,
+    /// Diameter of the dots drawn by `PolygonMode::Points`
+    pub point_size: f32,
+    /// Only the pixels inside this rectangle are drawn
+    pub scissor: Rect,
+}
+
+/// Triangle indices per tile, in submission order
+struct Bins {
+    tiles_x: i32,
+    tiles_y: i32,
+    bins: Vec<Vec<u32>>,
+}
+
+impl Bins {
+    /// `margin` grows every bounding box, for primitives that draw past their triangle.
+    /// Triangles are only binned into the tiles that overlap `scissor`.
+    fn new(framebuffer: &Framebuffer, tile_size: i32, triangles: &[[Vertex; 3]], margin: i32, scissor: Rect) -> Self {
+        let tiles_x = (framebuffer.width + tile_size - 1) / tile_size;
+        let tiles_y = (framebuffer.height + tile_size - 1) / tile_size;
+        let scissor = scissor.intersect(&Rect::from_size(framebuffer.width, framebuffer.height));
+        let mut bins = vec![Vec::new(); (tiles_x * tiles_y) as usize];
+
+        for (index, tri) in triangles.iter().enumerate() {
+            let xs = tri.iter().map(|v| v.transformed_position.x);
+            let ys = tri.iter().map(|v| v.transformed_position.y);
+            let min_x = xs.clone().fold(f32::INFINITY, f32::min).floor() as i32 - margin;
+            let max_x = xs.fold(f32::NEG_INFINITY, f32::max).ceil() as i32 + margin;
+            let min_y = ys.clone().fold(f32::INFINITY, f32::min).floor() as i32 - margin;
+            let max_y = ys.fold(f32::NEG_INFINITY, f32::max).ceil() as i32 + margin;
+
+            if max_x < scissor.x0 || max_y < scissor.y0 || min_x >= scissor.x1 || min_y >= scissor.y1 {
+                continue;
+            }
+
+            let first_tx = min_x.max(scissor.x0) / tile_size;
+            let last_tx = max_x.min(scissor.x1 - 1) / tile_size;
+            let first_ty = min_y.max(scissor.y0) / tile_size;
+            let last_ty = max_y.min(scissor.y1 - 1) / tile_size;
+
+            for ty in first_ty..=last_ty {
+                for tx in first_tx..=last_tx {
+                    bins[(ty * tiles_x + tx) as usize].push(index as u32);
+                }
+            }
+        }
+
+        Bins { tiles_x, tiles_y, bins }
+    }
+}
+
+impl TiledRasterizer {
+    pub fn new(tile_size: i32, threads: usize) -> Self {
+        TiledRasterizer {
+            tile_size,
+            threads: threads.max(1),
+        }
+    }
+
+    /// One worker per available core
+    pub fn with_available_parallelism(tile_size: i32) -> Self {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        TiledRasterizer::new(tile_size, threads)
+    }
+
+    /// Rasterizes, depth tests and shades the job's screen-space triangles into the
+    /// framebuffer as its polygon mode says, blending the shaded colors with its blend state
+    pub fn draw<S>(&self, framebuffer: &mut Framebuffer, job: &DrawJob<S>)
+    where
+        S: Fn(&mut Fragment) -> Vector4 + Sync,
+    {
+        if job.triangles.is_empty() || job.scissor.is_empty() {
+            return;
+        }
+
+        let margin = job.polygon_mode.margin(job.point_size);
+        let bins = Bins::new(framebuffer, self.tile_size, job.triangles, margin, job.scissor);
+        let bands = framebuffer.bands_mut(self.tile_size, job.scissor);
+
+        if self.threads == 1 {
+            for mut band in bands {
+                self.draw_band(&mut band, &bins, job);
+            }
+            return;
+        }
+
+        // Deal the bands out round-robin so every worker gets a mix of top and bottom rows
+        let mut work: Vec<Vec<FramebufferBand>> = (0..self.threads).map(|_| Vec::new()).collect();
+        for (i, band) in bands.into_iter().enumerate() {
+            work[i % self.threads].push(band);
+        }
+
+        thread::scope(|scope| {
+            for mut worker_bands in work {
+                let bins = &bins;
+                scope.spawn(move || {
+                    for band in &mut worker_bands {
+                        self.draw_band(band, bins, job);
+                    }
+                });
+            }
+        });
+    }
+
+    fn draw_band<S>(&self, band: &mut FramebufferBand, bins: &Bins, job: &DrawJob<S>)
+    where
+        S: Fn(&mut Fragment) -> Vector4,
+    {
+        let ty = band.y / self.tile_size;
+        if ty >= bins.tiles_y {
+            return;
+        }
+
+        if job.polygon_mode != PolygonMode::Fill {
+            self.draw_band_edges(band, bins, ty, job);
+            return;
+        }
+
+        for tx in 0..bins.tiles_x {
+            let tile = Rect::new(
+                tx * self.tile_size,
+                band.y,
+                ((tx + 1) * self.tile_size).min(band.width),
+                band.y + band.height,
+            )
+            .intersect(&job.scissor);
+            if tile.is_empty() {
+                continue;
+            }
+
+            for &index in &bins.bins[(ty * bins.tiles_x + tx) as usize] {
+                triangle(&job.triangles[index as usize], job.light, &tile, band, job.shader, job.depth_test, job.blend);
+            }
+        }
+    }
+
+    // Lines and points aren't clipped to tiles, so each edge or vertex of the band's row of
+    // tiles is drawn once, clipped to the band alone. Neighbouring triangles share edges and
+    // vertices, so only the first triangle in submission order draws each one.
+    fn draw_band_edges<S>(&self, band: &mut FramebufferBand, bins: &Bins, ty: i32, job: &DrawJob<S>)
+    where
+        S: Fn(&mut Fragment) -> Vector4,
+    {
+        let row = &bins.bins[(ty * bins.tiles_x) as usize..((ty + 1) * bins.tiles_x) as usize];
+        let mut indices: Vec<u32> = row.iter().flatten().copied().collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut drawn = HashSet::new();
+        for index in indices {
+            let tri = &job.triangles[index as usize];
+            for corner in 0..3 {
+                match job.polygon_mode {
+                    PolygonMode::Wireframe => {
+                        let next = (corner + 1) % 3;
+                        let (a, b) = (screen_key(&tri[corner]), screen_key(&tri[next]));
+                        if drawn.insert((a.min(b), a.max(b))) {
+                            wireframe::line(tri, (corner, next), job.light, band, job.shader, job.blend)
+                        }
+                    }
+                    _ => {
+                        let key = screen_key(&tri[corner]);
+                        if drawn.insert((key, key)) {
+                            wireframe::point(tri, corner, job.point_size, job.light, band, job.shader, job.blend)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Identifies a vertex by its exact screen position, so the copies of a shared
+// vertex in neighbouring triangles compare equal
+fn screen_key(vertex: &Vertex) -> [u32; 3] {
+    let p = vertex.transformed_position;
+    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::antialiasing::AntiAliasing;
+    use crate::asteroids::AsteroidBelt;
+    use crate::blend::Transparency;
+    use crate::camera::Camera;
+    use crate::culling::{CullMode, Culler, FrontFace};
+    use crate::obj::Obj;
+    use crate::pipeline::Pipeline;
+    use crate::{Extras, render_solar_system};
+    use crate::viewport::View;
+
+    // The whole system with every kind of draw: instanced bodies, the belt, the
+    // translucent atmosphere and the wireframe overlay, with 4x MSAA and OIT
+    fn render(rasterizer: TiledRasterizer, width: i32, height: i32) -> Framebuffer {
+        let mesh = Obj::load("assets/models/sphere.obj").expect("Failed to load obj");
+        let belt = AsteroidBelt::new(200);
+        let light = Light::new(Vector3::zero());
+        let camera = Camera::new(Vector3::new(0.0, 8.0, 20.0), Vector3::zero(), Vector3::new(0.0, 1.0, 0.0));
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        framebuffer.set_anti_aliasing(AntiAliasing::msaa(4).unwrap());
+        framebuffer.set_transparency(Transparency::OrderIndependent);
+        let mut pipeline = Pipeline::new(Culler::new(CullMode::Back, FrontFace::Clockwise), rasterizer);
+        pipeline.wireframe_overlay = true;
+
+        let views = [View::new(Rect::from_size(width, height), camera.get_view_matrix())];
+        render_solar_system(&mut framebuffer, &mut pipeline, &views, &mesh, Extras { belt: Some(&belt), atmosphere: true }, &light, 0.8);
+        framebuffer
+    }
+
+    #[test]
+    fn threads_match_the_single_threaded_path() {
+        // 150 rows isn't a multiple of either tile size, so the last band is shorter
+        for tile_size in [64, 48] {
+            let single = render(TiledRasterizer::new(tile_size, 1), 200, 150);
+            assert!(single.depth_buffer().iter().any(|depth| depth.is_finite()), "nothing was drawn");
+            for threads in [2, 3, 8] {
+                let parallel = render(TiledRasterizer::new(tile_size, threads), 200, 150);
+                assert!(single.color_buffer == parallel.color_buffer, "color differs with {} threads, {} px tiles", threads, tile_size);
+                assert!(
+                    single.depth_buffer().iter().map(|d| d.to_bits()).eq(parallel.depth_buffer().iter().map(|d| d.to_bits())),
+                    "depth differs with {} threads, {} px tiles",
+                    threads,
+                    tile_size
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+// tonemap.rs
+#![allow(dead_code)]
+
+use raylib::prelude::*;
+
+/// How HDR colors are squeezed into the displayable [0, 1] range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Cut every channel at 1, what the renderer always did
+    Clamp,
+    /// `c / (1 + c)`, never quite reaches white
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMapping {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(ToneMapping::Clamp),
+            "reinhard" => Some(ToneMapping::Reinhard),
+            "aces" => Some(ToneMapping::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapping::Clamp => "clamp",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+        }
+    }
+
+    /// The next operator, for cycling through them at runtime
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapping::Clamp => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::Clamp,
+        }
+    }
+
+    /// Maps an exposed HDR color to [0, 1]
+    #[inline]
+    pub fn apply(self, color: Vector3) -> Vector3 {
+        let curve = |c: f32| match self {
+            ToneMapping::Clamp => c,
+            ToneMapping::Reinhard => c / (1.0 + c),
+            ToneMapping::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+        };
+        Vector3::new(
+            curve(color.x.max(0.0)).clamp(0.0, 1.0),
+            curve(color.y.max(0.0)).clamp(0.0, 1.0),
+            curve(color.z.max(0.0)).clamp(0.0, 1.0),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapped(tone_mapping: ToneMapping, c: f32) -> f32 {
+        tone_mapping.apply(Vector3::new(c, c, c)).x
+    }
+
+    #[test]
+    fn clamp_cuts_at_one() {
+        assert_eq!(mapped(ToneMapping::Clamp, 0.5), 0.5);
+        assert_eq!(mapped(ToneMapping::Clamp, 2.5), 1.0);
+    }
+
+    #[test]
+    fn reinhard_and_aces_follow_their_curves() {
+        assert!((mapped(ToneMapping::Reinhard, 1.0) - 0.5).abs() < 1e-6);
+        assert!((mapped(ToneMapping::Reinhard, 3.0) - 0.75).abs() < 1e-6);
+        assert!((mapped(ToneMapping::Aces, 1.0) - 2.54 / 3.16).abs() < 1e-6);
+        assert_eq!(mapped(ToneMapping::Aces, 0.0), 0.0);
+        // ACES saturates to white, Reinhard only gets close
+        assert_eq!(mapped(ToneMapping::Aces, 100.0), 1.0);
+        assert!(mapped(ToneMapping::Reinhard, 100.0) < 1.0);
+    }
+
+    #[test]
+    fn negative_channels_map_to_black() {
+        for tone_mapping in [ToneMapping::Clamp, ToneMapping::Reinhard, ToneMapping::Aces] {
+            let color = tone_mapping.apply(Vector3::new(-1.0, -0.01, 0.0));
+            assert_eq!((color.x, color.y, color.z), (0.0, 0.0, 0.0));
+        }
+    }
+}
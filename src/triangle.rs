@@ -1,115 +1,471 @@
 // triangle.rs
+use crate::antialiasing::SAMPLE_GRID;
+use crate::blend::BlendState;
 use crate::fragment::Fragment;
+use crate::framebuffer::{DepthTestMode, FramebufferBand};
 use crate::vertex::Vertex;
-use crate::Vector3;
+use crate::{Vector2, Vector3, Vector4};
 use crate::light::Light;
+use crate::rect::Rect;
 
-fn barycentric_coordinates(p_x: f32, p_y: f32, a: &Vertex, b: &Vertex, c: &Vertex)  -> (f32, f32, f32) {
-    let a_x = a.transformed_position.x;   
-    let b_x = b.transformed_position.x;
-    let c_x = c.transformed_position.x;
-    let a_y = a.transformed_position.y;
-    let b_y = b.transformed_position.y;
-    let c_y = c.transformed_position.y;
+// Vertices are snapped to a 1/256 pixel grid before rasterizing
+const SUBPIXEL_BITS: i64 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const SUBPIXEL_HALF: i64 = SUBPIXEL_ONE / 2;
 
-    let area = (b_y - c_y) * (a_x - c_x) + (c_x - b_x) * (a_y - c_y);
+// Enough for 8x MSAA and 4x4 SSAA
+const MAX_SAMPLES: usize = 16;
 
-    if area.abs() < 1e-10  {
-        return (-1.0, -1.0, -1.0);
+#[derive(Clone, Copy)]
+struct FixedPoint {
+    x: i64,
+    y: i64,
+}
+
+impl FixedPoint {
+    fn snap(vertex: &Vertex) -> Self {
+        FixedPoint {
+            x: (vertex.transformed_position.x * SUBPIXEL_ONE as f32).round() as i64,
+            y: (vertex.transformed_position.y * SUBPIXEL_ONE as f32).round() as i64,
+        }
+    }
+}
+
+/// Edge function of `a -> b` evaluated at `p`; positive when `p` is on the
+/// inside of a triangle that is clockwise on screen (y grows downwards).
+#[inline]
+fn edge_function(a: FixedPoint, b: FixedPoint, p: FixedPoint) -> i64 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Top-left fill rule: a sample exactly on an edge only belongs to the
+/// triangle if that edge is a top edge or a left edge. Other edges get a bias
+/// of -1 so that their zero values fall outside.
+#[inline]
+fn fill_bias(a: FixedPoint, b: FixedPoint) -> i64 {
+    let dx = b.x - a.x;
+    let dy = b.y - a.y;
+    let is_top = dy == 0 && dx > 0;
+    let is_left = dy < 0;
+
+    if is_top || is_left { 0 } else { -1 }
+}
+
+/// Incrementally stepped edge function
+struct Edge {
+    step_x: i64,
+    step_y: i64,
+    row: i64,
+}
+
+impl Edge {
+    fn new(a: FixedPoint, b: FixedPoint, origin: FixedPoint) -> Self {
+        Edge {
+            step_x: -(b.y - a.y) * SUBPIXEL_ONE,
+            step_y: (b.x - a.x) * SUBPIXEL_ONE,
+            row: edge_function(a, b, origin) + fill_bias(a, b),
+        }
     }
-    
-    let w1 = ((b_y - c_y) * (p_x - c_x) + (c_x - b_x) * (p_y - c_y)) / area;
-    let w2= ((c_y - a_y) * (p_x - c_x) + (a_x - c_x) * (p_y - c_y)) / area;
-    let w3 = 1.0 - w1 - w2;
 
-    (w1, w2, w3)
+    /// How much the edge function changes from the pixel center to a sample offset
+    /// given in subpixel units; exact because the steps are whole multiples of a subpixel
+    fn offset(&self, dx: i64, dy: i64) -> i64 {
+        (self.step_x / SUBPIXEL_ONE) * dx + (self.step_y / SUBPIXEL_ONE) * dy
+    }
 }
 
-pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, light: &Light) -> Vec<Fragment> {
-    let mut fragments = Vec::new();
+fn perspective_correct(w1: f32, w2: f32, w3: f32, a: &Vertex, b: &Vertex, c: &Vertex) -> (f32, f32, f32) {
+    let p1 = w1 * a.inv_w;
+    let p2 = w2 * b.inv_w;
+    let p3 = w3 * c.inv_w;
+    let sum = p1 + p2 + p3;
+
+    if sum.abs() < 1e-10 {
+        return (w1, w2, w3);
+    }
+
+    (p1 / sum, p2 / sum, p3 / sum)
+}
+
+/// Interpolates the varyings and computes the lighting term for one covered sample
+pub fn build_fragment(p_x: f32, p_y: f32, depth: f32, (w1, w2, w3): (f32, f32, f32), [v1, v2, v3]: [&Vertex; 3], light: &Light) -> Fragment {
+    // Perspective-correct weights: attribute/w is linear in screen space,
+    // so weight by 1/w and renormalize by the interpolated 1/w
+    let (p1, p2, p3) = perspective_correct(w1, w2, w3, v1, v2, v3);
+
+    // Interpolate normals using the perspective-correct weights
+    let interpolated_normal = Vector3::new(
+        p1 * v1.transformed_normal.x + p2 * v2.transformed_normal.x + p3 * v3.transformed_normal.x,
+        p1 * v1.transformed_normal.y + p2 * v2.transformed_normal.y + p3 * v3.transformed_normal.y,
+        p1 * v1.transformed_normal.z + p2 * v2.transformed_normal.z + p3 * v3.transformed_normal.z,
+    );
+
+    // Normalize the interpolated normal
+    let normal_length = (interpolated_normal.x * interpolated_normal.x
+        + interpolated_normal.y * interpolated_normal.y
+        + interpolated_normal.z * interpolated_normal.z).sqrt();
+    let mut normalized_normal = interpolated_normal;
+    if normal_length > 0.0 {
+        normalized_normal.x /= normal_length;
+        normalized_normal.y /= normal_length;
+        normalized_normal.z /= normal_length;
+    }
+
+    // Calculate position in world space for this fragment
+    let world_pos = Vector3::new(
+        p1 * v1.world_position.x + p2 * v2.world_position.x + p3 * v3.world_position.x,
+        p1 * v1.world_position.y + p2 * v2.world_position.y + p3 * v3.world_position.y,
+        p1 * v1.world_position.z + p2 * v2.world_position.z + p3 * v3.world_position.z,
+    );
+
+    let tex_coords = Vector2::new(
+        p1 * v1.tex_coords.x + p2 * v2.tex_coords.x + p3 * v3.tex_coords.x,
+        p1 * v1.tex_coords.y + p2 * v2.tex_coords.y + p3 * v3.tex_coords.y,
+    );
+
+    // Light direction (from surface to light) for this fragment
+    let mut light_dir = Vector3::new(
+        light.position.x - world_pos.x,
+        light.position.y - world_pos.y,
+        light.position.z - world_pos.z,
+    );
+
+    // Normalize light direction
+    let light_length = (light_dir.x * light_dir.x + light_dir.y * light_dir.y + light_dir.z * light_dir.z).sqrt();
+    if light_length > 0.0 {
+        light_dir.x /= light_length;
+        light_dir.y /= light_length;
+        light_dir.z /= light_length;
+    }
+
+    // Calculate per-fragment lighting intensity using interpolated normal (Lambertian shading)
+    let diffuse = (normalized_normal.x * light_dir.x
+        + normalized_normal.y * light_dir.y
+        + normalized_normal.z * light_dir.z).max(0.0);
+
+    // Wrap-around lighting for softer terminator
+    let wrap = 0.4;
+    let wrapped_diffuse = (diffuse + wrap) / (1.0 + wrap);
+
+    // Add ambient light
+    let ambient = 0.2;
+    let intensity = ambient + wrapped_diffuse * (1.0 - ambient);
+
+    // Store intensity in color for the fragment shader to use
+    let lighting_color = Vector3::new(intensity, intensity, intensity);
+
+    let mut fragment = Fragment::new_with_varyings(
+        p_x,
+        p_y,
+        lighting_color,
+        depth,
+        normalized_normal,
+        world_pos,
+        tex_coords,
+    );
+    // Flat, every vertex of an instance carries the same parameters
+    fragment.params = v1.params;
+    fragment
+}
+
+/// Rasterizes the triangle straight into `target`, only touching pixels inside `bounds`.
+/// Uses fixed-point edge functions stepped incrementally across the bounding
+/// box, with the top-left rule so samples on shared edges are drawn exactly once.
+/// With `DepthTestMode::Early` fragments are depth tested before `shader` runs,
+/// so occluded ones are never shaded; `Late` is for shaders that change depth.
+///
+/// Coverage and depth are evaluated for every sample of the target's pattern.
+/// With multisampling the shader runs once per pixel at its center and the
+/// result goes to every covered sample; with supersampling each sample is shaded.
+/// The shader returns RGBA, which `blend` combines with the stored samples.
+pub fn triangle<S>(
+    tri: &[Vertex; 3],
+    light: &Light,
+    bounds: &Rect,
+    target: &mut FramebufferBand,
+    shader: &S,
+    depth_test: DepthTestMode,
+    blend: BlendState,
+) where
+    S: Fn(&mut Fragment) -> Vector4,
+{
+    let [v1, v2, v3] = tri;
+    let a = FixedPoint::snap(v1);
+    let mut b = FixedPoint::snap(v2);
+    let mut c = FixedPoint::snap(v3);
+    let (mut v2, mut v3) = (v2, v3);
+
+    // Twice the signed area in fixed point; degenerate once snapped means nothing to draw
+    let mut area = edge_function(a, b, c);
+    if area == 0 {
+        return;
+    }
+
+    // Culling already decided which side is visible, rasterize both windings the same way
+    if area < 0 {
+        std::mem::swap(&mut b, &mut c);
+        std::mem::swap(&mut v2, &mut v3);
+        area = -area;
+    }
+
+    // Get the bounding box of the triangle, clamped to the bounds we're allowed to write
+    let min_x = (a.x.min(b.x).min(c.x) >> SUBPIXEL_BITS).max(bounds.x0 as i64);
+    let max_x = (a.x.max(b.x).max(c.x) >> SUBPIXEL_BITS).min(bounds.x1 as i64 - 1);
+    let min_y = (a.y.min(b.y).min(c.y) >> SUBPIXEL_BITS).max(bounds.y0 as i64);
+    let max_y = (a.y.max(b.y).max(c.y) >> SUBPIXEL_BITS).min(bounds.y1 as i64 - 1);
+
+    if min_x > max_x || min_y > max_y {
+        return;
+    }
+
+    // Edge functions at the center of the first pixel, each weighting the opposite vertex
+    let origin = FixedPoint {
+        x: (min_x << SUBPIXEL_BITS) + SUBPIXEL_HALF,
+        y: (min_y << SUBPIXEL_BITS) + SUBPIXEL_HALF,
+    };
+    let mut e1 = Edge::new(b, c, origin);
+    let mut e2 = Edge::new(c, a, origin);
+    let mut e3 = Edge::new(a, b, origin);
 
-    // Get the bounding box of the triangle
-    let min_x = v1.transformed_position.x.min(v2.transformed_position.x).min(v3.transformed_position.x).floor() as i32;
-    let max_x = v1.transformed_position.x.max(v2.transformed_position.x).max(v3.transformed_position.x).ceil() as i32;
-    let min_y = v1.transformed_position.y.min(v2.transformed_position.y).min(v3.transformed_position.y).floor() as i32;
-    let max_y = v1.transformed_position.y.max(v2.transformed_position.y).max(v3.transformed_position.y).ceil() as i32;
+    // Per-sample edge offsets from the pixel center
+    let pattern = target.pattern;
+    let samples = pattern.sample_count().min(MAX_SAMPLES);
+    let mut sample_offsets = [(0i64, 0i64, 0i64); MAX_SAMPLES];
+    for (offsets, &(dx, dy)) in sample_offsets.iter_mut().zip(&pattern.offsets) {
+        let (dx, dy) = (dx as i64 * SUBPIXEL_ONE / SAMPLE_GRID as i64, dy as i64 * SUBPIXEL_ONE / SAMPLE_GRID as i64);
+        *offsets = (e1.offset(dx, dy), e2.offset(dx, dy), e3.offset(dx, dy));
+    }
+
+    let inv_area = 1.0 / area as f32;
+    let z = (v1.transformed_position.z, v2.transformed_position.z, v3.transformed_position.z);
 
-    // Iterate over each pixel in the bounding box
     for y in min_y..=max_y {
+        let mut w1 = e1.row;
+        let mut w2 = e2.row;
+        let mut w3 = e3.row;
+
         for x in min_x..=max_x {
-            let p_x = x as f32 + 0.5; // Sample at pixel center
-            let p_y = y as f32 + 0.5;
-
-            // Calculate barycentric coordinates
-            let (w1, w2, w3) = barycentric_coordinates(p_x, p_y, v1, v2, v3);
-
-            // Check if point is inside the triangle
-            if w1 >= 0.0 && w2 >= 0.0 && w3 >= 0.0 {
-                // Interpolate normals using barycentric coordinates
-                let interpolated_normal = Vector3::new(
-                    w1 * v1.transformed_normal.x + w2 * v2.transformed_normal.x + w3 * v3.transformed_normal.x,
-                    w1 * v1.transformed_normal.y + w2 * v2.transformed_normal.y + w3 * v3.transformed_normal.y,
-                    w1 * v1.transformed_normal.z + w2 * v2.transformed_normal.z + w3 * v3.transformed_normal.z,
-                );
-
-                // Normalize the interpolated normal
-                let normal_length = (interpolated_normal.x * interpolated_normal.x
-                    + interpolated_normal.y * interpolated_normal.y
-                    + interpolated_normal.z * interpolated_normal.z).sqrt();
-                let mut normalized_normal = interpolated_normal;
-                if normal_length > 0.0 {
-                    normalized_normal.x /= normal_length;
-                    normalized_normal.y /= normal_length;
-                    normalized_normal.z /= normal_length;
+            // Which samples lie inside the triangle
+            let mut covered = 0u32;
+            for (s, &(o1, o2, o3)) in sample_offsets[..samples].iter().enumerate() {
+                if ((w1 + o1) | (w2 + o2) | (w3 + o3)) >= 0 {
+                    covered |= 1 << s;
                 }
+            }
+
+            if covered != 0 {
+                let (x, y) = (x as i32, y as i32);
+                let mut sample_depths = [0.0f32; MAX_SAMPLES];
+
+                for s in 0..samples {
+                    if covered & (1 << s) == 0 {
+                        continue;
+                    }
+                    let (o1, o2, o3) = sample_offsets[s];
+                    // Barycentric coordinates, the fill bias is far below float precision
+                    let (l1, l2, l3) = ((w1 + o1) as f32 * inv_area, (w2 + o2) as f32 * inv_area, (w3 + o3) as f32 * inv_area);
+                    // Depth stays linear in screen space, so it uses the plain barycentrics
+                    let depth = l1 * z.0 + l2 * z.1 + l3 * z.2;
+                    sample_depths[s] = depth;
 
-                // Calculate position in world space for this fragment
-                let world_pos = Vector3::new(
-                    w1 * v1.world_position.x + w2 * v2.world_position.x + w3 * v3.world_position.x,
-                    w1 * v1.world_position.y + w2 * v2.world_position.y + w3 * v3.world_position.y,
-                    w1 * v1.world_position.z + w2 * v2.world_position.z + w3 * v3.world_position.z,
-                );
-
-                // Light direction (from surface to light) for this fragment
-                let mut light_dir = Vector3::new(
-                    light.position.x - world_pos.x,
-                    light.position.y - world_pos.y,
-                    light.position.z - world_pos.z,
-                );
-
-                // Normalize light direction
-                let light_length = (light_dir.x * light_dir.x + light_dir.y * light_dir.y + light_dir.z * light_dir.z).sqrt();
-                if light_length > 0.0 {
-                    light_dir.x /= light_length;
-                    light_dir.y /= light_length;
-                    light_dir.z /= light_length;
+                    if pattern.per_sample_shading {
+                        if depth_test == DepthTestMode::Late || target.depth_test(x, y, s, depth) {
+                            let (dx, dy) = pattern.offsets[s];
+                            let p_x = x as f32 + 0.5 + dx as f32 / SAMPLE_GRID as f32;
+                            let p_y = y as f32 + 0.5 + dy as f32 / SAMPLE_GRID as f32;
+                            let mut fragment = build_fragment(p_x, p_y, depth, (l1, l2, l3), [v1, v2, v3], light);
+                            let color = shader(&mut fragment);
+                            target.point(x, y, s, fragment.depth, color, blend);
+                        }
+                    } else if depth_test == DepthTestMode::Early && !target.depth_test(x, y, s, depth) {
+                        covered &= !(1 << s);
+                    }
                 }
 
-                // Calculate per-fragment lighting intensity using interpolated normal (Lambertian shading)
-                let diffuse = (normalized_normal.x * light_dir.x
-                    + normalized_normal.y * light_dir.y
-                    + normalized_normal.z * light_dir.z).max(0.0);
+                if !pattern.per_sample_shading && covered != 0 {
+                    // Shade once at the pixel center, even when the center itself is outside
+                    let (l1, l2, l3) = (w1 as f32 * inv_area, w2 as f32 * inv_area, w3 as f32 * inv_area);
+                    let depth = l1 * z.0 + l2 * z.1 + l3 * z.2;
+                    let mut fragment = build_fragment(x as f32 + 0.5, y as f32 + 0.5, depth, (l1, l2, l3), [v1, v2, v3], light);
+                    let color = shader(&mut fragment);
+                    // Shaders that move depth move every sample by the same amount
+                    let depth_offset = fragment.depth - depth;
+
+                    for (s, &sample_depth) in sample_depths[..samples].iter().enumerate() {
+                        if covered & (1 << s) != 0 {
+                            target.point(x, y, s, sample_depth + depth_offset, color, blend);
+                        }
+                    }
+                }
+            }
+
+            w1 += e1.step_x;
+            w2 += e2.step_x;
+            w3 += e3.step_x;
+        }
+
+        e1.row += e1.step_y;
+        e2.row += e2.step_y;
+        e3.row += e3.step_y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blend::BlendMode;
+    use crate::framebuffer::Framebuffer;
+
+    const SIZE: i32 = 8;
 
-                // Wrap-around lighting for softer terminator
-                let wrap = 0.4;
-                let wrapped_diffuse = (diffuse + wrap) / (1.0 + wrap);
-                
-                // Add ambient light
-                let ambient = 0.2;
-                let intensity = ambient + wrapped_diffuse * (1.0 - ambient);
+    // A screen-space triangle at a constant depth
+    fn flat_triangle(corners: [(f32, f32); 3], depth: f32) -> [Vertex; 3] {
+        corners.map(|(x, y)| {
+            let mut vertex = Vertex::new(Vector3::new(x, y, depth), Vector3::new(0.0, 0.0, 1.0), Vector2::zero());
+            vertex.set_transformed(Vector3::new(x, y, depth), vertex.normal);
+            vertex
+        })
+    }
 
-                // Store intensity in color for the fragment shader to use
-                let lighting_color = Vector3::new(intensity, intensity, intensity);
+    fn draw<S>(framebuffer: &mut Framebuffer, tri: &[Vertex; 3], shader: &S, depth_test: DepthTestMode, blend: BlendState)
+    where
+        S: Fn(&mut Fragment) -> Vector4,
+    {
+        let light = Light::new(Vector3::zero());
+        let bounds = Rect::from_size(SIZE, SIZE);
+        for mut band in framebuffer.bands_mut(SIZE, bounds) {
+            triangle(tri, &light, &bounds, &mut band, shader, depth_test, blend);
+        }
+    }
+
+    // How many of `triangles` cover each pixel: every one adds a quarter of red
+    fn coverage_counts(triangles: &[[Vertex; 3]]) -> Vec<u32> {
+        let mut framebuffer = Framebuffer::new(SIZE, SIZE);
+        let quarter = |_: &mut Fragment| Vector4::new(0.25, 0.0, 0.0, 1.0);
+        for tri in triangles {
+            draw(&mut framebuffer, tri, &quarter, DepthTestMode::Early, BlendState::transparent(BlendMode::Additive));
+        }
+        framebuffer.resolve();
+        framebuffer.color_buffer.chunks_exact(4).map(|pixel| (pixel[0] as f32 / 255.0 * 4.0).round() as u32).collect()
+    }
 
-                // Interpolate depth using barycentric coordinates
-                let depth = w1 * v1.transformed_position.z
-                    + w2 * v2.transformed_position.z
-                    + w3 * v3.transformed_position.z;
+    #[test]
+    fn top_left_rule_draws_shared_edges_once() {
+        let s = SIZE as f32;
+        // The diagonal runs through every pixel center on it
+        assert!(coverage_counts(&quad_halves(0.5)).iter().all(|&count| count == 1));
 
-                fragments.push(Fragment::new(p_x, p_y, lighting_color, depth));
+        // A vertical and a horizontal edge through pixel centers, with both windings
+        let (mid_x, mid_y) = (3.5, 4.5);
+        let rectangle = |x0: f32, y0: f32, x1: f32, y1: f32| {
+            [
+                flat_triangle([(x0, y0), (x1, y0), (x0, y1)], 0.5),
+                flat_triangle([(x1, y0), (x0, y1), (x1, y1)], 0.5),
+            ]
+        };
+        let pieces: Vec<[Vertex; 3]> = [
+            rectangle(0.0, 0.0, mid_x, mid_y),
+            rectangle(mid_x, 0.0, s, mid_y),
+            rectangle(0.0, mid_y, mid_x, s),
+            rectangle(mid_x, mid_y, s, s),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        assert!(coverage_counts(&pieces).iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn pixel_centers_on_a_right_or_bottom_edge_are_left_out() {
+        // Right edge at x = 3.5 and bottom edge at y = 2.5, both through pixel centers
+        let counts = coverage_counts(&[flat_triangle([(0.0, 0.0), (3.5, 0.0), (3.5, 2.5)], 0.5), flat_triangle([(0.0, 0.0), (3.5, 2.5), (0.0, 2.5)], 0.5)]);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let expected = (x < 3 && y < 2) as u32;
+                assert_eq!(counts[(y * SIZE + x) as usize], expected, "pixel {}, {}", x, y);
             }
         }
     }
 
-    fragments
+    // Covers the whole framebuffer
+    fn quad_halves(depth: f32) -> [[Vertex; 3]; 2] {
+        let s = SIZE as f32;
+        [flat_triangle([(0.0, 0.0), (s, 0.0), (0.0, s)], depth), flat_triangle([(s, 0.0), (s, s), (0.0, s)], depth)]
+    }
+
+    // Depth the shader writes when it pulls the fragment in front of everything
+    const SHADER_DEPTH: f32 = 0.1;
+
+    fn depth_after_moving_forward(depth_test: DepthTestMode) -> f32 {
+        let mut framebuffer = Framebuffer::new(SIZE, SIZE);
+        let opaque = |_: &mut Fragment| Vector4::new(1.0, 0.0, 0.0, 1.0);
+        for tri in &quad_halves(0.5) {
+            draw(&mut framebuffer, tri, &opaque, DepthTestMode::Early, BlendState::OPAQUE);
+        }
+
+        // Behind the first draw, but the shader moves it to the front
+        let moves_forward = |fragment: &mut Fragment| {
+            fragment.depth = SHADER_DEPTH;
+            Vector4::new(0.0, 1.0, 0.0, 1.0)
+        };
+        for tri in &quad_halves(0.8) {
+            draw(&mut framebuffer, tri, &moves_forward, depth_test, BlendState::OPAQUE);
+        }
+        framebuffer.depth_buffer()[(SIZE * SIZE / 2 + SIZE / 2) as usize]
+    }
+
+    #[test]
+    fn late_depth_test_uses_the_depth_the_shader_wrote() {
+        assert!((depth_after_moving_forward(DepthTestMode::Late) - SHADER_DEPTH).abs() < 1e-5);
+    }
+
+    #[test]
+    fn early_depth_test_skips_fragments_before_shading() {
+        assert!((depth_after_moving_forward(DepthTestMode::Early) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn varyings_are_perspective_correct_and_depth_is_linear() {
+        // Right triangle over the whole target, its x corner four times as far away as the others
+        let s = SIZE as f32;
+        let mut tri = flat_triangle([(0.0, 0.0), (s, 0.0), (0.0, s)], 0.0);
+        let corners = [(0.2, 1.0), (0.8, 0.25), (0.5, 1.0)];
+        for (index, (vertex, (depth, inv_w))) in tri.iter_mut().zip(corners).enumerate() {
+            vertex.transformed_position.z = depth;
+            vertex.inv_w = inv_w;
+            vertex.tex_coords = Vector2::new((index == 1) as u8 as f32, (index == 2) as u8 as f32);
+            vertex.world_position = Vector3::new(vertex.tex_coords.x, vertex.tex_coords.y, 0.0) * 4.0;
+        }
+
+        // Position, interpolated depth, texture coordinates and world position of every fragment
+        let fragments = std::cell::RefCell::new(Vec::new());
+        let record = |fragment: &mut Fragment| {
+            fragments.borrow_mut().push((fragment.position, fragment.depth, fragment.tex_coords, fragment.world_position));
+            Vector4::new(1.0, 1.0, 1.0, 1.0)
+        };
+        draw(&mut Framebuffer::new(SIZE, SIZE), &tri, &record, DepthTestMode::Early, BlendState::OPAQUE);
+
+        let fragments = fragments.into_inner();
+        assert!(!fragments.is_empty());
+        let mut largest_difference: f32 = 0.0;
+        for (position, depth, tex_coords, world_position) in fragments {
+            // Affine barycentrics of the pixel center, plain screen-space interpolation
+            let (l2, l3) = (position.x / s, position.y / s);
+            let l1 = 1.0 - l2 - l3;
+            let affine_depth = l1 * corners[0].0 + l2 * corners[1].0 + l3 * corners[2].0;
+            assert!((depth - affine_depth).abs() < 1e-5, "depth {} at {:?}, expected {}", depth, position, affine_depth);
+
+            // The same weights divided by w, then renormalized
+            let (p1, p2, p3) = (l1 * corners[0].1, l2 * corners[1].1, l3 * corners[2].1);
+            let sum = p1 + p2 + p3;
+            let expected = Vector2::new(p2 / sum, p3 / sum);
+            assert!((tex_coords - expected).length() < 1e-5, "tex_coords {:?} at {:?}, expected {:?}", tex_coords, position, expected);
+            let world = Vector2::new(world_position.x, world_position.y);
+            assert!((world - expected * 4.0).length() < 1e-4, "world_position {:?} at {:?}", world_position, position);
+
+            largest_difference = largest_difference.max((tex_coords - Vector2::new(l2, l3)).length());
+        }
+        // Affine interpolation would be visibly off
+        assert!(largest_difference > 0.1, "perspective correction barely changed anything: {}", largest_difference);
+    }
 }
@@ -1,5 +1,5 @@
 #![allow(dead_code)]
-use raylib::math::{Vector2, Vector3};
+use raylib::math::{Vector2, Vector3, Vector4};
 
 #[derive(Clone, Debug)]
 pub struct Vertex {
@@ -10,6 +10,10 @@ pub struct Vertex {
   pub transformed_position: Vector3,
   pub transformed_normal: Vector3,
   pub world_position: Vector3,
+  pub clip_position: Vector4,
+  pub inv_w: f32,
+  /// Per-instance shader parameters, the same on every vertex of an instance
+  pub params: Vector4,
 }
 
 impl Vertex {
@@ -22,6 +26,9 @@ impl Vertex {
       transformed_position: position,
       transformed_normal: normal,
       world_position: Vector3::new(0.0, 0.0, 0.0),
+      clip_position: Vector4::new(position.x, position.y, position.z, 1.0),
+      inv_w: 1.0,
+      params: Vector4::new(0.0, 0.0, 0.0, 0.0),
     }
   }
 
@@ -34,6 +41,9 @@ impl Vertex {
       transformed_position: Vector3::new(0.0, 0.0, 0.0),
       transformed_normal: Vector3::new(0.0, 0.0, 0.0),
       world_position: Vector3::new(0.0, 0.0, 0.0),
+      clip_position: Vector4::new(position.x, position.y, position.z, 1.0),
+      inv_w: 1.0,
+      params: Vector4::new(0.0, 0.0, 0.0, 0.0),
     }
   }
 
@@ -41,6 +51,23 @@ impl Vertex {
     self.transformed_position = position;
     self.transformed_normal = normal;
   }
+
+  /// Linearly interpolates every attribute towards `other`.
+  /// Used by the clipper to build the vertices where an edge crosses a plane.
+  pub fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+    Vertex {
+      position: self.position.lerp(other.position, t),
+      normal: self.normal.lerp(other.normal, t),
+      tex_coords: self.tex_coords.lerp(other.tex_coords, t),
+      color: self.color.lerp(other.color, t),
+      transformed_position: self.transformed_position.lerp(other.transformed_position, t),
+      transformed_normal: self.transformed_normal.lerp(other.transformed_normal, t),
+      world_position: self.world_position.lerp(other.world_position, t),
+      clip_position: self.clip_position.lerp(other.clip_position, t),
+      inv_w: self.inv_w + (other.inv_w - self.inv_w) * t,
+      params: self.params,
+    }
+  }
 }
 
 impl Default for Vertex {
@@ -52,7 +79,10 @@ impl Default for Vertex {
       color: Vector3::new(0.0, 0.0, 0.0),
       transformed_position: Vector3::new(0.0, 0.0, 0.0),
       transformed_normal: Vector3::new(0.0, 1.0, 0.0),
-      world_position: Vector3::zero()
+      world_position: Vector3::zero(),
+      clip_position: Vector4::new(0.0, 0.0, 0.0, 1.0),
+      inv_w: 1.0,
+      params: Vector4::new(0.0, 0.0, 0.0, 0.0),
     }
   }
 }
@@ -0,0 +1,92 @@
+// viewport.rs
+#![allow(dead_code)]
+
+use crate::matrix::create_viewport_matrix;
+use crate::rect::Rect;
+use raylib::prelude::*;
+
+/// One camera drawn into a rectangle of the framebuffer
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    pub rect: Rect,
+    pub view_matrix: Matrix,
+}
+
+impl View {
+    pub fn new(rect: Rect, view_matrix: Matrix) -> Self {
+        View { rect, view_matrix }
+    }
+
+    /// Width over height of the rectangle, for the projection
+    pub fn aspect_ratio(&self) -> f32 {
+        self.rect.width() as f32 / self.rect.height().max(1) as f32
+    }
+
+    /// Maps normalized device coordinates onto the rectangle
+    pub fn viewport_matrix(&self) -> Matrix {
+        create_viewport_matrix(
+            self.rect.x0 as f32,
+            self.rect.y0 as f32,
+            self.rect.width() as f32,
+            self.rect.height() as f32,
+        )
+    }
+}
+
+/// How the overview and the close-up camera share the framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewLayout {
+    /// The overview alone, on the whole framebuffer
+    Single,
+    /// The overview on the left half, the close-up on the right half
+    SideBySide,
+    /// The overview on the whole framebuffer, the close-up in a small inset at the bottom right
+    PictureInPicture,
+}
+
+impl ViewLayout {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "single" => Some(ViewLayout::Single),
+            "side-by-side" => Some(ViewLayout::SideBySide),
+            "picture-in-picture" | "pip" => Some(ViewLayout::PictureInPicture),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewLayout::Single => "single",
+            ViewLayout::SideBySide => "side-by-side",
+            ViewLayout::PictureInPicture => "picture-in-picture",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ViewLayout::Single => ViewLayout::SideBySide,
+            ViewLayout::SideBySide => ViewLayout::PictureInPicture,
+            ViewLayout::PictureInPicture => ViewLayout::Single,
+        }
+    }
+
+    /// Rectangles of a `width` x `height` framebuffer, the overview's first.
+    /// Later rectangles are drawn over the earlier ones.
+    pub fn rects(self, width: i32, height: i32) -> Vec<Rect> {
+        match self {
+            ViewLayout::Single => vec![Rect::from_size(width, height)],
+            ViewLayout::SideBySide => {
+                let half = width / 2;
+                vec![Rect::new(0, 0, half, height), Rect::new(half, 0, width, height)]
+            }
+            ViewLayout::PictureInPicture => {
+                let (inset_width, inset_height) = (width / 3, height / 3);
+                let margin = width.min(height) / 40;
+                vec![
+                    Rect::from_size(width, height),
+                    Rect::new(width - margin - inset_width, height - margin - inset_height, width - margin, height - margin),
+                ]
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+// wireframe.rs
+#![allow(dead_code)]
+
+use crate::blend::BlendState;
+use crate::fragment::Fragment;
+use crate::framebuffer::FramebufferBand;
+use crate::light::Light;
+use crate::matrix::VIEWPORT_DEPTH_SCALE;
+use crate::triangle::build_fragment;
+use crate::vertex::Vertex;
+use raylib::prelude::*;
+
+// Lines and points are pulled towards the camera by this fraction of their distance
+// to the far end of the depth range, so they win the depth test against the surface
+// they lie on. Roughly constant in pixels, since depth precision falls off with distance.
+const DEPTH_BIAS: f32 = 0.003;
+
+/// How a draw's triangles are turned into pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolygonMode {
+    /// Filled triangles, the normal way of drawing
+    Fill,
+    /// Anti-aliased lines along every triangle edge
+    Wireframe,
+    /// A round dot on every vertex
+    Points,
+}
+
+impl PolygonMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fill" => Some(PolygonMode::Fill),
+            "wireframe" => Some(PolygonMode::Wireframe),
+            "points" => Some(PolygonMode::Points),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PolygonMode::Fill => "fill",
+            PolygonMode::Wireframe => "wireframe",
+            PolygonMode::Points => "points",
+        }
+    }
+
+    /// How far past a triangle's bounding box the mode can draw, in pixels
+    pub fn margin(self, point_size: f32) -> i32 {
+        match self {
+            PolygonMode::Fill => 0,
+            PolygonMode::Wireframe => 1,
+            PolygonMode::Points => (point_size * 0.5).ceil() as i32 + 1,
+        }
+    }
+}
+
+#[inline]
+fn biased_depth(depth: f32) -> f32 {
+    depth - (VIEWPORT_DEPTH_SCALE - depth).max(0.0) * DEPTH_BIAS
+}
+
+// Writes one shaded pixel to every sample, weighted by how much the primitive covers it.
+// Opaque draws mix by coverage through their own write path, blended ones scale their alpha.
+// Pixels outside the band are dropped by the band.
+#[inline]
+fn plot(target: &mut FramebufferBand, x: i32, y: i32, depth: f32, color: Vector4, coverage: f32, blend: BlendState) {
+    if coverage <= 0.0 {
+        return;
+    }
+    let coverage = coverage.min(1.0);
+    for sample in 0..target.pattern.sample_count() {
+        if blend.is_transparent() {
+            target.point(x, y, sample, depth, Vector4::new(color.x, color.y, color.z, color.w * coverage), blend);
+        } else {
+            target.coverage_point(x, y, sample, depth, Vector3::new(color.x, color.y, color.z), coverage);
+        }
+    }
+}
+
+/// Draws the edge from vertex `from` to vertex `to` of `tri` as an anti-aliased,
+/// one pixel wide line into the band.
+/// Steps along the major axis and splits each step between the two nearest pixels
+/// by distance, Wu style. The varyings are interpolated along the edge and `shader`
+/// runs once per step.
+pub fn line<S>(
+    tri: &[Vertex; 3],
+    (from, to): (usize, usize),
+    light: &Light,
+    target: &mut FramebufferBand,
+    shader: &S,
+    blend: BlendState,
+) where
+    S: Fn(&mut Fragment) -> Vector4,
+{
+    let a = tri[from].transformed_position;
+    let b = tri[to].transformed_position;
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let steep = dy.abs() > dx.abs();
+    let (major_start, major_length, minor_start, minor_length) =
+        if steep { (a.y, dy, a.x, dx) } else { (a.x, dx, a.y, dy) };
+
+    if major_length.abs() < 1e-6 {
+        return;
+    }
+
+    // Pixel centers along the major axis that lie on the segment
+    let first = (major_start.min(major_start + major_length) - 0.5).ceil() as i32;
+    let last = (major_start.max(major_start + major_length) - 0.5).floor() as i32;
+    // Skip the rows outside the band when stepping down the screen
+    let (first, last) = if steep { (first.max(target.y), last.min(target.y + target.height - 1)) } else { (first, last) };
+
+    for major in first..=last {
+        let t = (major as f32 + 0.5 - major_start) / major_length;
+        let minor = minor_start + minor_length * t - 0.5;
+        let minor_pixel = minor.floor();
+        let fraction = minor - minor_pixel;
+        if !steep && (minor_pixel as i32 + 1 < target.y || minor_pixel as i32 >= target.y + target.height) {
+            continue;
+        }
+
+        let mut weights = [0.0f32; 3];
+        weights[from] = 1.0 - t;
+        weights[to] = t;
+        let depth = biased_depth(a.z + (b.z - a.z) * t);
+        let (p_x, p_y) = if steep { (minor + 0.5, major as f32 + 0.5) } else { (major as f32 + 0.5, minor + 0.5) };
+        let mut fragment = build_fragment(p_x, p_y, depth, (weights[0], weights[1], weights[2]), [&tri[0], &tri[1], &tri[2]], light);
+        let color = shader(&mut fragment);
+
+        for (offset, coverage) in [(0, 1.0 - fraction), (1, fraction)] {
+            let minor_pixel = minor_pixel as i32 + offset;
+            let (x, y) = if steep { (minor_pixel, major) } else { (major, minor_pixel) };
+            plot(target, x, y, fragment.depth, color, coverage, blend);
+        }
+    }
+}
+
+/// Draws vertex `index` of `tri` as an anti-aliased dot `size` pixels across,
+/// into the band. `shader` runs once, at the vertex.
+pub fn point<S>(
+    tri: &[Vertex; 3],
+    index: usize,
+    size: f32,
+    light: &Light,
+    target: &mut FramebufferBand,
+    shader: &S,
+    blend: BlendState,
+) where
+    S: Fn(&mut Fragment) -> Vector4,
+{
+    let center = tri[index].transformed_position;
+    let radius = size * 0.5;
+
+    let min_x = ((center.x - radius - 0.5).floor() as i32).max(0);
+    let max_x = ((center.x + radius - 0.5).ceil() as i32).min(target.width - 1);
+    let min_y = ((center.y - radius - 0.5).floor() as i32).max(target.y);
+    let max_y = ((center.y + radius - 0.5).ceil() as i32).min(target.y + target.height - 1);
+    if min_x > max_x || min_y > max_y {
+        return;
+    }
+
+    let mut weights = [0.0f32; 3];
+    weights[index] = 1.0;
+    let depth = biased_depth(center.z);
+    let mut fragment = build_fragment(center.x, center.y, depth, (weights[0], weights[1], weights[2]), [&tri[0], &tri[1], &tri[2]], light);
+    let color = shader(&mut fragment);
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let distance = ((x as f32 + 0.5 - center.x).powi(2) + (y as f32 + 0.5 - center.y).powi(2)).sqrt();
+            // One pixel wide ramp at the rim
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            plot(target, x, y, fragment.depth, color, coverage, blend);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+// golden.rs
+use std::process::Command;
+
+/// Renders the golden scenes headlessly and compares them with the references in assets/golden.
+/// After an intended change in the output, refresh them with `cargo run --release -- --golden-update`.
+#[test]
+fn golden_images_match() {
+    let output = Command::new(env!("CARGO_BIN_EXE_shaders"))
+        .arg("--golden-check")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Failed to run the renderer");
+
+    assert!(
+        output.status.success(),
+        "golden images differ:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}